@@ -2,7 +2,8 @@
 MODEL_NAME="<CAMPAIGN NAME>"
 IP_ADDRESS="127.0.0.1:8080"
 ################# Ad collector parameters #################
-ACCESS_TOKEN="<SECRET TOKEN>"
+# File holding the access token ... keeps the token off the command line and out of this script
+ACCESS_TOKEN_FILE="<PATH TO TOKEN FILE>"
 PAGE_IDS="id1,id2,id3,..."
 
 YEAR_START=2020
@@ -27,7 +28,7 @@ echo "Running Ad Collector"
 echo "##########################################################"
 cd data_collector/
 SAVE_PATH="./web/data/${MODEL_NAME}"
-cargo run --release collect --save_path=$SAVE_PATH --access_token=$ACCESS_TOKEN --ad_status=$AD_STATUS --page_ids=$PAGE_IDS --batch_size=$BATCH_SIZE --retries=$RETRIES --year_start=$YEAR_START --month_start=$MONTH_START --day_start=$DAY_START --year_end=$YEAR_END --month_end=$MONTH_END --day_end=$DAY_END
+cargo run --release collect --save_path=$SAVE_PATH --access_token_file=$ACCESS_TOKEN_FILE --ad_status=$AD_STATUS --page_ids=$PAGE_IDS --batch_size=$BATCH_SIZE --retries=$RETRIES --year_start=$YEAR_START --month_start=$MONTH_START --day_start=$DAY_START --year_end=$YEAR_END --month_end=$MONTH_END --day_end=$DAY_END
 
 # Run analysis scripts on the ad data
 NLP_MODEL_PATH="${SAVE_PATH}/models" 
@@ -0,0 +1,98 @@
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::{OpenOptions, DirBuilder};
+use std::path::{Path, PathBuf};
+use std::io::Write;
+
+use super::{Collection, SchemaObservations, parse_ad};
+
+// Name of the append-only archive of raw ads written next to the collected data
+pub const ARCHIVE_FILE: &str = "raw_ads.ndjson";
+
+// Line of the archive ... an ad exactly as the API returned it along with the country it was collected for
+//   Copies returned for other countries are archived too so reaggregate knows every country an ad reached.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedAd {
+    pub country: String,
+    pub collected_at: DateTime<Utc>,
+    pub ad: Value,
+    // Delivery start times (inclusive) of the collection ... set on rejected ads, whose start time collection couldn't check,
+    // so a copy parsed by reaggregate is only kept if collection would have kept it
+    #[serde(default)]
+    pub window: Option<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+// Appends ads to the archive, one JSON object per line
+pub fn append_archive(ads: &[ArchivedAd], path: &Path) -> std::io::Result<()> {
+    if ads.is_empty() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        DirBuilder::new().recursive(true).create(parent)?;
+    }
+    let mut lines = String::new();
+    for ad in ads.iter() {
+        lines.push_str(&serde_json::to_string(ad).unwrap());
+        lines.push('\n');
+    }
+    // Written at once so a crash never leaves half an ad behind
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(lines.as_bytes())
+}
+
+// Moves the archive of an earlier collection aside (eg. raw_ads.20201018T120000.ndjson) so a fresh collection starts a new one
+//   Returns where the earlier archive was moved to, if there was one.
+pub fn rotate_archive(path: &Path) -> std::io::Result<Option<PathBuf>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let rotated = path.with_extension(format!("{}.ndjson", Utc::now().format("%Y%m%dT%H%M%S")));
+    fs::rename(path, &rotated)?;
+    Ok(Some(rotated))
+}
+
+// Rebuilds a collection from the archive with the current parsing rules ... no request is made to the API
+//   Copies are handled as during collection: the first country an ad was collected for is kept, copies for
+//   other countries are only noted in reached_countries, and later copies for that country replace earlier ones
+//   as they hold more recent figures.
+pub fn reaggregate(path: &Path) -> std::io::Result<Collection> {
+    let content = fs::read_to_string(path)?;
+    let mut collection = Collection { records: HashMap::new(), rejects: Vec::new(), schema: SchemaObservations::default() };
+    for (line_number, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let archived: ArchivedAd = serde_json::from_str(line)
+                                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("line {}: {}", line_number + 1, e)))?;
+        match parse_ad(archived.ad, &archived.country) {
+            Ok(mut record) => {
+                record.collected_at = Some(archived.collected_at);
+                let keep = match (collection.records.get_mut(&record.id), archived.window) {
+                    (Some(existing), _) if existing.country != record.country => {
+                        existing.reached_countries.insert(archived.country.clone());
+                        false
+                    },
+                    (Some(existing), _) => {
+                        record.reached_countries.extend(existing.reached_countries.iter().cloned());
+                        true
+                    },
+                    (None, Some((start, end))) => record.ad_delivery_start_time >= start && record.ad_delivery_start_time <= end,
+                    (None, None) => true,
+                };
+                if keep {
+                    collection.records.insert(record.id.clone(), record);
+                }
+            },
+            Err(rejected) => {
+                let rejects = &mut collection.rejects;
+                if rejected.id.is_none() || !rejects.iter().any(|other| other.id == rejected.id && other.country == rejected.country) {
+                    rejects.push(rejected);
+                }
+            },
+        }
+    }
+    Ok(collection)
+}
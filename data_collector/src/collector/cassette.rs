@@ -0,0 +1,124 @@
+use chrono::{DateTime, Utc};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use serde::{Deserialize, Serialize};
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::fs::{File, DirBuilder};
+use std::path::{Path, PathBuf};
+use std::io::Write;
+
+// Raw API response exactly as it was received ... one is saved per file while recording
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    // Endpoint that was requested (without the access token)
+    pub endpoint: String,
+    pub received_at: DateTime<Utc>,
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+impl RecordedResponse {
+    pub fn header_map(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.headers.iter() {
+            if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+                headers.insert(name, value);
+            }
+        }
+        headers
+    }
+}
+
+// Responses are matched on path and query alone so pages recorded against one host (eg. a mock) can be replayed with another base url
+fn replay_key(endpoint: &str) -> String {
+    match reqwest::Url::parse(endpoint) {
+        Ok(url) => match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        },
+        Err(_) => endpoint.to_string(),
+    }
+}
+
+// Recorded responses are numbered in the order they were received (eg. 000001.json)
+fn recorded_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+                                    .filter_map(|entry| entry.ok())
+                                    .map(|entry| entry.path())
+                                    .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+                                    .collect();
+    files.sort();
+    Ok(files)
+}
+
+// Saves every response received into a directory
+#[derive(Debug)]
+pub struct Recorder {
+    dir: PathBuf,
+    // Number of the next file ... continues after files already in the directory so resumed collections append to the recording
+    next: Cell<usize>,
+}
+
+impl Recorder {
+    pub fn new(dir: &Path) -> std::io::Result<Recorder> {
+        DirBuilder::new().recursive(true).create(dir)?;
+        let next = recorded_files(dir)?.len() + 1;
+        Ok(Recorder { dir: dir.to_path_buf(), next: Cell::new(next) })
+    }
+
+    pub fn record(&self, response: &RecordedResponse) -> std::io::Result<()> {
+        let path = self.dir.join(format!("{:06}.json", self.next.get()));
+        self.next.set(self.next.get() + 1);
+        let mut file = File::create(path)?;
+        file.write_all(serde_json::to_string_pretty(response).unwrap().as_bytes())?;
+        Ok(())
+    }
+}
+
+// Serves recorded responses in place of the API
+#[derive(Debug)]
+pub struct Replayer {
+    // Responses to an endpoint that was requested more than once (eg. after an error) are served in the order they were recorded
+    responses: RefCell<HashMap<String, VecDeque<RecordedResponse>>>,
+}
+
+impl Replayer {
+    pub fn load(dir: &Path) -> std::io::Result<Replayer> {
+        let mut responses: HashMap<String, VecDeque<RecordedResponse>> = HashMap::new();
+        for path in recorded_files(dir)?.iter() {
+            let content = fs::read_to_string(path)?;
+            let response: RecordedResponse = serde_json::from_str(&content)
+                                                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+            responses.entry(replay_key(&response.endpoint)).or_default().push_back(response);
+        }
+        Ok(Replayer { responses: RefCell::new(responses) })
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.responses.borrow().values().map(VecDeque::len).sum()
+    }
+
+    pub fn next(&self, endpoint: &str) -> Option<RecordedResponse> {
+        self.responses.borrow_mut().get_mut(&replay_key(endpoint)).and_then(VecDeque::pop_front)
+    }
+}
+
+// Whether API responses are saved to or served from disk
+#[derive(Debug, Default)]
+pub enum Cassette {
+    #[default]
+    Off,
+    Record(Recorder),
+    Replay(Replayer),
+}
+
+impl Cassette {
+    pub fn replaying(&self) -> bool {
+        matches!(self, Cassette::Replay(_))
+    }
+}
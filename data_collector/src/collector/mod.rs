@@ -0,0 +1,1089 @@
+use chrono::{DateTime, Utc, NaiveDate, Datelike, Duration as DateDuration};
+
+use serde::{Deserialize, Serialize};
+
+use futures::stream::StreamExt;
+
+use std::fmt;
+use std::fmt::{Formatter, Error};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::fs::{File, DirBuilder};
+use std::path::{Path, PathBuf};
+use std::io::Write;
+use std::time::Duration;
+
+mod records;
+mod pacing;
+mod schema;
+mod cassette;
+mod archive;
+mod report;
+
+pub use records::{AdRecord, AdMetric, RejectedAd, OpenRange, aggregate, collected_as_of, load_records, merge_results, save_results, save_rejects, RECORDS_FILE, REJECTS_FILE};
+pub use schema::{SchemaObservations, save_schema_drift, SCHEMA_DRIFT_FILE};
+pub use cassette::{Cassette, Recorder, Replayer};
+pub use archive::{reaggregate, rotate_archive, ARCHIVE_FILE};
+pub use report::{import_report, save_report, load_summary, REPORT_DATA_FILE};
+
+use pacing::{Pacer, Usage};
+use cassette::RecordedResponse;
+use archive::{ArchivedAd, append_archive};
+
+// Name of the file written next to the collected data that allows collection to be resumed
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+// Graph API version and ad fields requested unless configured otherwise
+pub const DEFAULT_API_VERSION: &str = "v5.0";
+pub const DEFAULT_FIELDS: &[&str] = &[
+    "id", "page_id", "page_name", "funding_entity",
+    "ad_creative_body", "ad_creative_link_title", "ad_creative_link_description", "ad_creative_link_caption", "languages",
+    "ad_delivery_start_time", "ad_delivery_stop_time",
+    "demographic_distribution", "impressions", "region_distribution", "spend", "currency", "publisher_platforms",
+];
+// Fields every ad needs ... always requested even when left out of a configured field list
+pub const REQUIRED_FIELDS: &[&str] = &["id", "ad_delivery_start_time"];
+
+fn default_api_version() -> String {
+    DEFAULT_API_VERSION.to_string()
+}
+
+fn default_fields() -> Vec<String> {
+    DEFAULT_FIELDS.iter().map(|field| field.to_string()).collect()
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Serialize, Deserialize)]
+pub enum AdStatus {
+    ALL,
+    ACTIVE,
+    INACTIVE,
+}
+
+impl fmt::Display for AdStatus {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            AdStatus::ALL => {
+                write!(f, "ALL")
+            },
+            AdStatus::ACTIVE => {
+                write!(f, "ACTIVE")
+            },
+            AdStatus::INACTIVE => {
+                write!(f, "INACTIVE")
+            },
+        }
+    }
+}
+
+// Length of the date ranges a collection is split into ... each slice is paged through independently
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Serialize, Deserialize)]
+pub enum DateSlice {
+    WHOLE,
+    MONTH,
+    WEEK,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Serialize, Deserialize)]
+pub enum AdType {
+    ALL,
+    POLITICAL_AND_ISSUE_ADS,
+    HOUSING_ADS,
+    EMPLOYMENT_ADS,
+    CREDIT_ADS,
+}
+
+impl fmt::Display for AdType {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            AdType::ALL => {
+                write!(f, "ALL")
+            },
+            AdType::POLITICAL_AND_ISSUE_ADS => {
+                write!(f, "POLITICAL_AND_ISSUE_ADS")
+            },
+            AdType::HOUSING_ADS => {
+                write!(f, "HOUSING_ADS")
+            },
+            AdType::EMPLOYMENT_ADS => {
+                write!(f, "EMPLOYMENT_ADS")
+            },
+            AdType::CREDIT_ADS => {
+                write!(f, "CREDIT_ADS")
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ApiDemographic {
+    age: String,
+    gender: String,
+    percentage: String
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ApiCountRange {
+    lower_bound: Option<String>,
+    upper_bound: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ApiRegion {
+    region: String,
+    percentage: String,
+}
+
+// Every field but the id may be missing ... fields are configurable and change between API versions
+#[derive(Debug, Serialize, Deserialize)]
+struct ApiAdData {
+    id: String,
+    page_id: Option<String>,
+    page_name: Option<String>,
+    funding_entity: Option<String>,
+    ad_creative_body: Option<String>,
+    ad_creative_link_title: Option<String>,
+    ad_creative_link_description: Option<String>,
+    ad_creative_link_caption: Option<String>,
+    languages: Option<Vec<String>>,
+    ad_delivery_start_time: Option<String>,
+    ad_delivery_stop_time: Option<String>,
+    demographic_distribution: Option<Vec<ApiDemographic>>,
+    impressions: Option<ApiCountRange>,
+    region_distribution: Option<Vec<ApiRegion>>,
+    spend: Option<ApiCountRange>,
+    currency: Option<String>,
+    publisher_platforms: Option<Vec<String>>,
+    // Fields the collector doesn't model ... kept as returned so API changes don't silently lose data
+    #[serde(flatten)]
+    extra_fields: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Cursor {
+    next: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ApiResponse {
+    // Ads are parsed one at a time so a single malformed ad doesn't fail the whole page
+    data: Vec<serde_json::Value>,
+    // Last page may come without a paging cursor
+    #[serde(default)]
+    paging: Cursor,
+    // Only looked at to report fields the API started returning
+    #[serde(flatten)]
+    extra_fields: serde_json::Map<String, serde_json::Value>,
+}
+
+// Error object returned by the Graph API in place of an ApiResponse
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiError {
+    pub message: String,
+    #[serde(rename = "type")]
+    pub error_type: Option<String>,
+    pub code: i64,
+    pub error_subcode: Option<i64>,
+    pub fbtrace_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ApiErrorResponse {
+    error: ApiError,
+}
+
+// How the collector should react to an ApiError
+#[derive(Debug, PartialEq)]
+enum ApiErrorKind {
+    // Access token expired or was revoked ... retrying will never succeed
+    InvalidToken,
+    // App, user, or business use case hit its request quota
+    RateLimited,
+    // Page of ads was too large for the API to return
+    ReduceData,
+    Other,
+}
+
+impl ApiError {
+    fn kind(&self) -> ApiErrorKind {
+        match self.code {
+            190 => ApiErrorKind::InvalidToken,
+            4 | 17 | 613 => ApiErrorKind::RateLimited,
+            1 if self.message.to_lowercase().contains("reduce the amount of data") => ApiErrorKind::ReduceData,
+            _ => ApiErrorKind::Other,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "code {}", self.code)?;
+        if let Some(error_subcode) = self.error_subcode {
+            write!(f, ", subcode {}", error_subcode)?;
+        }
+        if let Some(error_type) = &self.error_type {
+            write!(f, ", type {}", error_type)?;
+        }
+        if let Some(fbtrace_id) = &self.fbtrace_id {
+            write!(f, ", fbtrace_id {}", fbtrace_id)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Collector {
+    // Obtain ads that were posted within time frame
+    pub start_date_time: DateTime<Utc>,
+    pub end_date_time: DateTime<Utc>,
+    pub ad_status: AdStatus,
+    pub ad_type: AdType,
+    // ISO country codes ... each country is collected separately so ads can be attributed to the country they reached
+    pub countries: Vec<String>,
+    // Ads are searched by page and/or by keywords ... at least one of the two is given
+    pub page_ids: Vec<u64>,
+    pub search_terms: Option<String>,
+    // Match search_terms as an exact phrase instead of as unordered keywords
+    pub exact_phrase: bool,
+    // Access tokens are supplied on every run and never written to a checkpoint
+    //   Tokens are used in order ... once a token is rejected as invalid or expired, the next one is used.
+    #[serde(skip)]
+    pub access_tokens: Vec<String>,
+    pub retries: usize,
+    pub batch_size: usize,
+    // Seconds to wait after being rate limited ... doubles with every consecutive retry
+    pub backoff_secs: u64,
+    // Seconds to wait before retrying any other failed request ... doubles with every consecutive retry
+    pub retry_delay_secs: u64,
+    // Quota usage (percent) reported by the API at which requests start being slowed down
+    pub usage_threshold: f64,
+    // Graph API version (eg. v5.0) and the ad fields requested ... configurable so a sunset version can be replaced without a code change
+    #[serde(default = "default_api_version")]
+    pub api_version: String,
+    #[serde(default = "default_fields")]
+    pub fields: Vec<String>,
+    // Scheme and host of the Graph API ... may point to a mock Ad Library for offline collection
+    pub base_url: String,
+    // Collect each page id as its own query instead of searching all pages at once
+    pub split_pages: bool,
+    // Number of queries collected at the same time
+    pub concurrency: usize,
+    pub date_slice: DateSlice,
+    // Start at user-provided endpoint for the first query ... may be used to continue progress after failure
+    pub endpoint: Option<String>,
+    // Ads from an earlier collection that had already stopped being delivered (incremental collections only)
+    //   A query stops paging once it reaches a page holding nothing but these ads.
+    #[serde(default)]
+    pub finished_ads: HashSet<String>,
+    // Location of the checkpoint that is updated after every page
+    #[serde(skip)]
+    pub checkpoint_path: Option<PathBuf>,
+    // Append-only archive every collected ad is written to exactly as returned ... lets ad_data.json be rebuilt without the API
+    #[serde(skip)]
+    pub archive_path: Option<PathBuf>,
+    // Saves every API response to disk or serves them from an earlier recording instead of the network
+    #[serde(skip)]
+    pub cassette: Cassette,
+}
+
+// Reasons collection can stop before reaching the end of the requested time frame
+#[derive(Debug)]
+pub enum CollectError {
+    // Request could not be sent or the response could not be read (eg. dropped connection, TLS failure)
+    Transport(reqwest::Error),
+    // API kept responding with content that is not an ApiResponse until retries ran out
+    InvalidResponse(serde_json::Error),
+    // API responded with an error object that could not be recovered from
+    Api(ApiError),
+    // Every access token was rejected
+    NoAccessToken,
+    // Replayed collection requested an endpoint the recording holds no (more) responses for
+    NotRecorded(String),
+}
+
+impl fmt::Display for CollectError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            CollectError::Transport(e) => {
+                // Error message includes the url that was requested ... along with its access token
+                write!(f, "Transport error: {}", redact_access_token(&e.to_string()))
+            },
+            CollectError::InvalidResponse(e) => {
+                write!(f, "Invalid response: {}", e)
+            },
+            CollectError::Api(e) => {
+                match e.kind() {
+                    ApiErrorKind::InvalidToken => write!(f, "Access token is invalid or expired ({})", e),
+                    ApiErrorKind::RateLimited => write!(f, "Rate limited by the API ({})", e),
+                    _ => write!(f, "API error ({})", e),
+                }
+            },
+            CollectError::NoAccessToken => {
+                write!(f, "Every access token was rejected as invalid or expired")
+            },
+            CollectError::NotRecorded(endpoint) => {
+                write!(f, "No recorded response left for {}", endpoint)
+            },
+        }
+    }
+}
+
+// Ads gathered by a collection
+pub struct Collection {
+    pub records: HashMap<String, AdRecord>,
+    // Ads the API returned that could not be parsed
+    pub rejects: Vec<RejectedAd>,
+    // Fields seen in the responses ... compared with the expected fields once collection ends
+    pub schema: SchemaObservations,
+}
+
+// Ads collected before collection was stopped by an error
+pub struct PartialCollection {
+    pub collection: Collection,
+    // Endpoint that was being requested when the error occurred
+    pub endpoint: String,
+    pub error: CollectError,
+}
+
+// Part of a collection that is paged through on its own
+//   Every country is its own query so ads can be attributed to the country they reached. Page ids
+//   are either searched together or split into a query per page.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Query {
+    pub country: String,
+    pub page_ids: Vec<u64>,
+    // Delivery dates (inclusive) sent to the API so only ads delivered within the slice are returned
+    pub date_min: NaiveDate,
+    pub date_max: NaiveDate,
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "{}", self.country)?;
+        if !self.page_ids.is_empty() {
+            write!(f, " pages={:?}", self.page_ids)?;
+        }
+        write!(f, " {}..{}", self.date_min, self.date_max)
+    }
+}
+
+// Progress of a query ... endpoint is the next page to collect and None once the query is finished
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueryState {
+    pub query: Query,
+    pub endpoint: Option<String>,
+}
+
+// State of an unfinished collection ... holds everything needed to pick up where collection stopped
+#[derive(Deserialize)]
+pub struct Checkpoint {
+    // Parameters the collection was started with
+    pub collector: Collector,
+    pub queries: Vec<QueryState>,
+    // Ads collected so far
+    pub results: HashMap<String, AdRecord>,
+    #[serde(default)]
+    pub rejects: Vec<RejectedAd>,
+    #[serde(default)]
+    pub schema: SchemaObservations,
+}
+
+// Borrowed form of Checkpoint so the partial results don't need to be cloned on every page
+#[derive(Serialize)]
+struct CheckpointRef<'a> {
+    collector: &'a Collector,
+    queries: &'a [QueryState],
+    results: &'a HashMap<String, AdRecord>,
+    rejects: &'a [RejectedAd],
+    schema: &'a SchemaObservations,
+}
+
+// Query index, time spent waiting, and where the query stopped if it failed
+type QueryOutcome = (usize, Duration, Result<(), (String, CollectError)>);
+
+// Progress shared by the queries being collected at the same time
+struct CollectionState {
+    queries: Vec<QueryState>,
+    collection: Collection,
+    // Index of the access token currently in use
+    token: usize,
+    // Number of pages collected with each access token
+    token_pages: Vec<usize>,
+    // Ads collected or updated so far ... an ad returned again by a later slice or page isn't counted twice
+    seen: HashSet<String>,
+}
+
+fn query_param(endpoint: &str, param: &str) -> Option<String> {
+    let url = reqwest::Url::parse(endpoint).ok()?;
+    let value = url.query_pairs().find(|(key, _)| key == param).map(|(_, value)| value.into_owned());
+    value
+}
+
+// Sets a query parameter of an endpoint, replacing any previous value
+fn set_query_param(endpoint: &str, param: &str, value: &str) -> String {
+    match reqwest::Url::parse(endpoint) {
+        Ok(mut url) => {
+            let pairs: Vec<(String, String)> = url.query_pairs()
+                                                  .filter(|(key, _)| key != param)
+                                                  .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                                                  .collect();
+            url.query_pairs_mut()
+               .clear()
+               .extend_pairs(pairs)
+               .append_pair(param, value);
+            url.into_string()
+        },
+        Err(_) => {
+            endpoint.to_string()
+        },
+    }
+}
+
+// Removes a query parameter from an endpoint
+fn remove_query_param(endpoint: &str, param: &str) -> String {
+    match reqwest::Url::parse(endpoint) {
+        Ok(mut url) => {
+            let pairs: Vec<(String, String)> = url.query_pairs()
+                                                  .filter(|(key, _)| key != param)
+                                                  .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                                                  .collect();
+            if pairs.is_empty() {
+                url.set_query(None);
+            } else {
+                url.query_pairs_mut().clear().extend_pairs(pairs);
+            }
+            url.into_string()
+        },
+        Err(_) => {
+            endpoint.to_string()
+        },
+    }
+}
+
+// Endpoints are kept without the access token so it never ends up in logs or checkpoints ... the token
+// is only added right before a request is sent. Paging cursors returned by the API embed the token.
+pub fn strip_access_token(endpoint: &str) -> String {
+    if query_param(endpoint, "access_token").is_some() {
+        remove_query_param(endpoint, "access_token")
+    } else {
+        endpoint.to_string()
+    }
+}
+
+// Replaces anything in a message that looks like an access token in a url
+fn redact_access_token(message: &str) -> String {
+    let mut redacted = String::new();
+    let mut rest = message;
+    while let Some(idx) = rest.find("access_token=") {
+        let (before, after) = rest.split_at(idx + "access_token=".len());
+        redacted.push_str(before);
+        redacted.push_str("REDACTED");
+        let token_len = after.find(|c: char| c == '&' || c == ')' || c == '"' || c == '\\' || c.is_whitespace()).unwrap_or(after.len());
+        rest = &after[token_len..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+
+// Splits a time frame (inclusive) into consecutive date ranges ... slices line up with calendar weeks and months
+fn date_slices(start: NaiveDate, end: NaiveDate, date_slice: &DateSlice) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut slices: Vec<(NaiveDate, NaiveDate)> = Vec::new();
+    let mut slice_start = start;
+    while slice_start <= end {
+        let slice_end = match date_slice {
+            DateSlice::WHOLE => end,
+            DateSlice::MONTH => {
+                let (year, month) = if slice_start.month() == 12 { (slice_start.year() + 1, 1) } else { (slice_start.year(), slice_start.month() + 1) };
+                NaiveDate::from_ymd(year, month, 1).pred()
+            },
+            DateSlice::WEEK => {
+                // Weeks end on Sunday
+                slice_start + DateDuration::days(6 - i64::from(slice_start.weekday().num_days_from_monday()))
+            },
+        };
+        let slice_end = std::cmp::min(slice_end, end);
+        slices.push((slice_start, slice_end));
+        slice_start = slice_end.succ();
+    }
+    slices
+}
+
+impl Collector {
+    // Collects ads on top of the records of an earlier collection ... these are empty unless collecting incrementally
+    pub async fn collect(&self, records: HashMap<String, AdRecord>) -> Result<Collection, PartialCollection> {
+        let mut queries: Vec<QueryState> = self.queries()
+                                               .into_iter()
+                                               .map(|query| QueryState { endpoint: Some(self.initial_endpoint(&query)), query })
+                                               .collect();
+        if let (Some(endpoint), Some(first)) = (&self.endpoint, queries.first_mut()) {
+            first.endpoint = Some(strip_access_token(endpoint));
+        }
+        self.collect_queries(queries, Collection { records, rejects: Vec::new(), schema: SchemaObservations::default() }).await
+    }
+
+    // Continues an unfinished collection using the current access token
+    pub async fn resume(&self, mut queries: Vec<QueryState>, collection: Collection) -> Result<Collection, PartialCollection> {
+        // Checkpoints written by earlier versions still hold the token in their endpoints
+        for state in queries.iter_mut() {
+            state.endpoint = state.endpoint.as_deref().map(strip_access_token);
+        }
+        self.collect_queries(queries, collection).await
+    }
+
+    fn queries(&self) -> Vec<Query> {
+        let page_groups: Vec<Vec<u64>> = if self.split_pages && !self.page_ids.is_empty() {
+            self.page_ids.iter().map(|page_id| vec![*page_id]).collect()
+        } else {
+            vec![self.page_ids.clone()]
+        };
+
+        let date_slices = date_slices(self.start_date_time.naive_utc().date(), self.end_date_time.naive_utc().date(), &self.date_slice);
+
+        let mut queries: Vec<Query> = Vec::new();
+        for country in self.countries.iter() {
+            for page_ids in page_groups.iter() {
+                for (date_min, date_max) in date_slices.iter() {
+                    queries.push(Query {
+                        country: country.clone(),
+                        page_ids: page_ids.clone(),
+                        date_min: *date_min,
+                        date_max: *date_max,
+                    });
+                }
+            }
+        }
+        queries
+    }
+
+    fn initial_endpoint(&self, query: &Query) -> String {
+        let mut params: Vec<(&str, String)> = vec![
+            ("fields", self.fields.join(",")),
+            ("ad_type", self.ad_type.to_string()),
+            ("ad_reached_countries", format!("['{}']", query.country)),
+            ("ad_active_status", self.ad_status.to_string()),
+            ("ad_delivery_date_min", query.date_min.format("%Y-%m-%d").to_string()),
+            ("ad_delivery_date_max", query.date_max.format("%Y-%m-%d").to_string()),
+        ];
+        if !query.page_ids.is_empty() {
+            params.push(("search_page_ids", format!("{:?}", query.page_ids)));
+        }
+        if let Some(search_terms) = &self.search_terms {
+            params.push(("search_terms", format!("'{}'", search_terms)));
+            params.push(("search_type", String::from(if self.exact_phrase { "KEYWORD_EXACT_PHRASE" } else { "KEYWORD_UNORDERED" })));
+        }
+        params.push(("limit", self.batch_size.to_string()));
+
+        // Let the url encode search terms as they may hold spaces and quotes
+        let endpoint = format!("{}/{}/ads_archive", self.base_url.trim_end_matches('/'), self.api_version);
+        match reqwest::Url::parse_with_params(&endpoint, &params) {
+            Ok(url) => url.into_string(),
+            Err(_) => endpoint,
+        }
+    }
+
+    fn write_checkpoint(&self, state: &CollectionState) -> std::io::Result<()> {
+        if let Some(checkpoint_path) = &self.checkpoint_path {
+            if let Some(parent) = checkpoint_path.parent() {
+                DirBuilder::new().recursive(true).create(parent)?;
+            }
+            let checkpoint = CheckpointRef {
+                collector: self,
+                queries: &state.queries,
+                results: &state.collection.records,
+                rejects: &state.collection.rejects,
+                schema: &state.collection.schema,
+            };
+            // Write to a temporary file first so a crash mid-write never corrupts the previous checkpoint
+            let temp_path = checkpoint_path.with_extension("json.tmp");
+            let mut file = File::create(&temp_path)?;
+            file.write_all(serde_json::to_string(&checkpoint).unwrap().as_bytes())?;
+            fs::rename(&temp_path, checkpoint_path)?;
+        }
+        Ok(())
+    }
+
+    // Collection reached the end of the requested time frame ... checkpoint is no longer needed
+    fn clear_checkpoint(&self) {
+        if let Some(checkpoint_path) = &self.checkpoint_path {
+            if checkpoint_path.exists() && fs::remove_file(checkpoint_path).is_err() {
+                println!("Failed to remove checkpoint: {}", checkpoint_path.display());
+            }
+        }
+    }
+
+    // Returns the response body along with the quota usage reported in its headers
+    async fn fetch(&self, client: &reqwest::Client, endpoint: &str, access_token: &str) -> Result<(String, Usage), CollectError> {
+        if let Cassette::Replay(replayer) = &self.cassette {
+            return match replayer.next(endpoint) {
+                Some(response) => {
+                    let usage = Usage::from_headers(&response.header_map());
+                    Ok((response.body, usage))
+                },
+                None => Err(CollectError::NotRecorded(endpoint.to_string())),
+            };
+        }
+
+        let response = client.get(&set_query_param(endpoint, "access_token", access_token)).send().await.map_err(CollectError::Transport)?;
+        let usage = Usage::from_headers(response.headers());
+        let status = response.status().as_u16();
+        let headers: BTreeMap<String, String> = response.headers()
+                                                        .iter()
+                                                        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+                                                        .collect();
+        let body = response.text().await.map_err(CollectError::Transport)?;
+        if let Cassette::Record(recorder) = &self.cassette {
+            // Paging cursors in the body carry the access token ... keep it out of the recording
+            //   Only access_token= parameters are redacted as a short token could otherwise match any text in the body.
+            let recorded = RecordedResponse { endpoint: endpoint.to_string(), received_at: Utc::now(), status, headers, body: redact_access_token(&body) };
+            if let Err(e) = recorder.record(&recorded) {
+                println!("Failed to record response: {}", e);
+            }
+        }
+        Ok((body, usage))
+    }
+
+    // Collects unfinished queries with at most self.concurrency of them running at the same time
+    async fn collect_queries(&self, queries: Vec<QueryState>, collection: Collection) -> Result<Collection, PartialCollection> {
+        let client = reqwest::Client::new();
+        let pending: Vec<usize> = (0..queries.len()).filter(|idx| queries[*idx].endpoint.is_some()).collect();
+        let state = RefCell::new(CollectionState {
+            queries,
+            collection,
+            token: 0,
+            token_pages: vec![0; self.access_tokens.len()],
+            seen: HashSet::new(),
+        });
+        if let Err(e) = self.write_checkpoint(&state.borrow()) {
+            println!("Failed to write checkpoint: {}", e);
+        }
+
+        let outcomes: Vec<QueryOutcome> =
+            futures::stream::iter(pending)
+                .map(|idx| {
+                    let client = &client;
+                    let state = &state;
+                    async move {
+                        let mut pacer = Pacer {
+                            usage_threshold: self.usage_threshold,
+                            max_pause: Duration::from_secs(self.backoff_secs),
+                            waited: Duration::from_secs(0),
+                            skip_waits: self.cassette.replaying(),
+                        };
+                        let outcome = self.collect_query(client, &mut pacer, idx, state).await;
+                        (idx, pacer.waited, outcome)
+                    }
+                })
+                .buffer_unordered(std::cmp::max(self.concurrency, 1))
+                .collect()
+                .await;
+
+        let state = state.into_inner();
+        let mut waited = Duration::from_secs(0);
+        let mut failure: Option<(String, CollectError)> = None;
+        for (idx, query_waited, outcome) in outcomes.into_iter() {
+            waited += query_waited;
+            match outcome {
+                Ok(()) => {
+                    println!("[{}] Finished", state.queries[idx].query);
+                },
+                Err((endpoint, error)) => {
+                    println!("[{}] Stopped: {}", state.queries[idx].query, error);
+                    // Report the first failure ... checkpoint holds where every other query stopped
+                    if failure.is_none() {
+                        failure = Some((endpoint, error));
+                    }
+                },
+            }
+        }
+        println!("Waited {:.1} seconds in total for rate limits and retries", waited.as_secs_f64());
+        for (token, pages) in state.token_pages.iter().enumerate().filter(|(_, pages)| **pages > 0) {
+            println!("Access token #{} was used for {} pages", token + 1, pages);
+        }
+
+        match failure {
+            Some((endpoint, error)) => {
+                Err(PartialCollection { collection: state.collection, endpoint, error })
+            },
+            None => {
+                self.clear_checkpoint();
+                Ok(state.collection)
+            },
+        }
+    }
+
+    // Pages through a single query ... returns the failing endpoint when collection stops early
+    async fn collect_query(&self, client: &reqwest::Client, pacer: &mut Pacer, idx: usize, state: &RefCell<CollectionState>) -> Result<(), (String, CollectError)> {
+        let query = state.borrow().queries[idx].query.clone();
+        let country = query.country.as_str();
+        let mut endpoint = match state.borrow().queries[idx].endpoint.clone() {
+            Some(endpoint) => endpoint,
+            None => return Ok(()),
+        };
+        let mut retries: usize = 0;
+        let mut pages: usize = 0;
+        let mut collected: usize = 0;
+        let mut updated: usize = 0;
+        // Page the current access token started being used on by this query
+        let mut token_first_page: usize = 1;
+        loop {
+            let token = state.borrow().token;
+            let access_token = match self.access_tokens.get(token) {
+                Some(access_token) => access_token,
+                None => {
+                    return Err((endpoint, CollectError::NoAccessToken));
+                },
+            };
+            println!("[{}] Endpoint: {}", query, endpoint);
+            let (api_response_content, usage): (String, Usage) = match self.fetch(client, &endpoint, access_token).await {
+                Ok(response) => response,
+                Err(e) => {
+                    return Err((endpoint, e));
+                },
+            };
+            let api_response_result: Result<ApiResponse, serde_json::Error> = serde_json::from_str(&api_response_content);
+            match api_response_result {
+                Ok(api_response) => {
+                    // Reset retry counter
+                    retries = 0;
+                    pages += 1;
+                    {
+                        let mut state = state.borrow_mut();
+                        state.token_pages[token] += 1;
+                        let schema = &mut state.collection.schema;
+                        schema.observe_response(&api_response.extra_fields);
+                        for ad in api_response.data.iter() {
+                            schema.observe_ad(ad);
+                        }
+                    }
+                    let parsed: Vec<Result<AdRecord, RejectedAd>> = api_response.data.iter().cloned().map(|ad| parse_ad(ad, country)).collect();
+                    let start_times: Vec<&DateTime<Utc>> = parsed.iter().filter_map(|ad| ad.as_ref().ok()).map(|record| &record.ad_delivery_start_time).collect();
+                    if let (Some(first), Some(last)) = (start_times.first(), start_times.last()) {
+                        println!("[{}] From {} to {}", query, first.format("%Y-%m-%d"), last.format("%Y-%m-%d"));
+                    }
+
+                    let reached_finished_ads = !parsed.is_empty() && parsed.iter().all(|ad| {
+                        ad.as_ref().is_ok_and(|record| self.finished_ads.contains(&record.id))
+                    });
+
+                    let next_endpoint = {
+                        let mut state = state.borrow_mut();
+                        // Raw copies of the ads kept by the collection (and of ads seen again for another country) ... written to the archive once the page is done
+                        let mut archived: Vec<ArchivedAd> = Vec::new();
+                        let collected_at = Utc::now();
+                        for (raw_ad, ad) in api_response.data.iter().zip(parsed) {
+                            let mut record = match ad {
+                                Ok(record) => record,
+                                Err(rejected) => {
+                                    println!("[{}] Rejected ad {}: {}", query, rejected.id.as_deref().unwrap_or("without id"), rejected.reason);
+                                    // Known ads are kept whenever they started ... others only if they started within the time frame
+                                    let known = rejected.id.as_ref().is_some_and(|id| state.collection.records.contains_key(id));
+                                    let window = if known { None } else { Some((self.start_date_time, self.end_date_time)) };
+                                    // Same ad may be returned again by another slice or page query
+                                    let rejects = &mut state.collection.rejects;
+                                    if rejected.id.is_none() || !rejects.iter().any(|other| other.id == rejected.id && other.country == rejected.country) {
+                                        rejects.push(rejected);
+                                        archived.push(ArchivedAd { country: country.to_string(), collected_at, ad: raw_ad.clone(), window });
+                                    }
+                                    continue;
+                                },
+                            };
+                            let state = &mut *state;
+                            match state.collection.records.get_mut(&record.id) {
+                                // Ads that reached several countries are returned once per country ... keep the first copy so they are counted once
+                                //   Records saved before countries were kept are taken to match.
+                                Some(existing) if existing.country.as_deref().is_some_and(|existing| existing != country) => {
+                                    // Note the country so regions of the ad aren't attributed to the first one ... archived for reaggregate to do the same
+                                    existing.reached_countries.insert(country.to_string());
+                                    archived.push(ArchivedAd { country: country.to_string(), collected_at, ad: raw_ad.clone(), window: None });
+                                    continue;
+                                },
+                                // Ad was already collected ... replace it as the latest copy holds the most recent impression counts
+                                Some(existing) => {
+                                    record.reached_countries.extend(existing.reached_countries.iter().cloned());
+                                    if state.seen.insert(record.id.clone()) {
+                                        updated += 1;
+                                    }
+                                },
+                                // Consider only ads that started within specified time frame ... the API also returns
+                                // ads that started earlier but were still being delivered during the slice
+                                None => {
+                                    if record.ad_delivery_start_time < self.start_date_time || record.ad_delivery_start_time > self.end_date_time {
+                                        continue;
+                                    }
+                                    state.seen.insert(record.id.clone());
+                                    collected += 1;
+                                },
+                            }
+                            record.collected_at = Some(collected_at);
+                            state.collection.records.insert(record.id.clone(), record);
+                            archived.push(ArchivedAd { country: country.to_string(), collected_at, ad: raw_ad.clone(), window: None });
+                        }
+                        if let Some(archive_path) = &self.archive_path {
+                            if let Err(e) = append_archive(&archived, archive_path) {
+                                println!("Failed to archive ads: {}", e);
+                            }
+                        }
+
+                        // No next page once the slice has been exhausted or only ads known to be finished remain
+                        let next_endpoint = if reached_finished_ads {
+                            println!("[{}] Reached ads that were already collected", query);
+                            None
+                        } else {
+                            api_response.paging.next.as_deref().map(strip_access_token)
+                        };
+                        state.queries[idx].endpoint = next_endpoint.clone();
+                        if let Err(e) = self.write_checkpoint(&state) {
+                            println!("Failed to write checkpoint: {}", e);
+                        }
+                        next_endpoint
+                    };
+                    println!("[{}] {} pages, {} new ads collected, {} ads updated", query, pages, collected, updated);
+
+                    match next_endpoint {
+                        Some(next_endpoint) => {
+                            endpoint = next_endpoint;
+                            pacer.pace(usage).await;
+                        },
+                        None => {
+                            println!("[{}] Access token #{} was used for pages {} to {}", query, token + 1, token_first_page, pages);
+                            return Ok(());
+                        },
+                    }
+                },
+                Err(e) => {
+                    // Response was not a page of ads ... check whether the API reported an error
+                    let error = match serde_json::from_str::<ApiErrorResponse>(&api_response_content) {
+                        Ok(error_response) => CollectError::Api(error_response.error),
+                        Err(_) => CollectError::InvalidResponse(e),
+                    };
+                    println!("[{}] Error: {}", query, error);
+                    let kind = match &error {
+                        CollectError::Api(api_error) => api_error.kind(),
+                        _ => ApiErrorKind::Other,
+                    };
+                    if kind == ApiErrorKind::InvalidToken {
+                        if token_first_page <= pages {
+                            println!("[{}] Access token #{} was used for pages {} to {}", query, token + 1, token_first_page, pages);
+                        }
+                        token_first_page = pages + 1;
+                        // Another query may have already moved on from this token
+                        let next_token = {
+                            let mut state = state.borrow_mut();
+                            if state.token == token {
+                                state.token += 1;
+                            }
+                            state.token
+                        };
+                        if next_token >= self.access_tokens.len() {
+                            // Leave checkpoint in place so collection can be resumed
+                            return Err((endpoint, error));
+                        }
+                        println!("[{}] Access token #{} was rejected ... continuing with access token #{}", query, token + 1, next_token + 1);
+                        continue;
+                    }
+                    // Leave checkpoint in place so collection can be resumed
+                    if retries >= self.retries {
+                        return Err((endpoint, error));
+                    }
+                    retries += 1;
+                    println!("[{}] Failed ... Retrying({}/{})", query, retries, self.retries);
+
+                    match kind {
+                        ApiErrorKind::RateLimited => {
+                            let regain_access = Duration::from_secs(usage.regain_access_minutes * 60);
+                            pacer.backoff(Duration::from_secs(self.backoff_secs), retries, regain_access, "Rate limited").await;
+                        },
+                        ApiErrorKind::ReduceData => {
+                            // Paging cursors carry the limit forward so later pages stay small
+                            let batch_size = query_param(&endpoint, "limit").and_then(|limit| limit.parse::<usize>().ok()).unwrap_or(self.batch_size);
+                            let batch_size = std::cmp::max(batch_size / 2, 1);
+                            println!("[{}] Reducing page size to {}", query, batch_size);
+                            endpoint = set_query_param(&endpoint, "limit", &batch_size.to_string());
+                            // Checkpoint the smaller page so a resumed collection doesn't go back to the size that failed
+                            {
+                                let mut state = state.borrow_mut();
+                                state.queries[idx].endpoint = Some(endpoint.clone());
+                                if let Err(e) = self.write_checkpoint(&state) {
+                                    println!("Failed to write checkpoint: {}", e);
+                                }
+                            }
+                            pacer.backoff(Duration::from_secs(self.retry_delay_secs), retries, Duration::from_secs(0), "Retrying").await;
+                        },
+                        _ => {
+                            pacer.backoff(Duration::from_secs(self.retry_delay_secs), retries, Duration::from_secs(0), "Retrying").await;
+                        },
+                    }
+                },
+            }
+        }
+    }
+}
+
+// Facebook changed api format before ... accept full timestamps (eg. 2020-03-28T12:00:00+0000 or RFC3339)
+// as well as bare dates, which are normalized to midnight UTC time
+fn parse_api_date(date: &str) -> Result<DateTime<Utc>, String> {
+    let date = date.trim();
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(date) {
+        return Ok(DateTime::from(date_time));
+    }
+    if let Ok(date_time) = DateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%z") {
+        return Ok(DateTime::from(date_time));
+    }
+    match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(date) => Ok(DateTime::from_utc(date.and_hms(0, 0, 0), Utc)),
+        Err(_) => Err(format!("Unrecognized date {:?}", date)),
+    }
+}
+
+// Parses a single ad returned by the API ... ads that are malformed are rejected rather than failing the page
+fn parse_ad(ad: serde_json::Value, country: &str) -> Result<AdRecord, RejectedAd> {
+    let parsed = serde_json::from_value::<ApiAdData>(ad.clone())
+                    .map_err(|e| e.to_string())
+                    .and_then(|api_ad| AdRecord::from_api(&api_ad, country));
+    parsed.map_err(|reason| RejectedAd {
+        id: ad.get("id").and_then(serde_json::Value::as_str).map(String::from),
+        country: country.to_string(),
+        reason,
+        ad,
+    })
+}
+
+
+// Reads the checkpoint left behind by an unfinished collection
+pub fn load_checkpoint(path: &Path) -> std::io::Result<Checkpoint> {
+    let content = fs::read_to_string(path)?;
+    serde_json::from_str(&content).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    use serde_json::{json, Value};
+
+    fn ad(percentage: &str) -> Value {
+        json!({
+            "id": "2000000000000023",
+            "page_id": "222",
+            "ad_creative_body": "Caucus night is almost here!",
+            "ad_delivery_start_time": "2020-02-02",
+            "ad_delivery_stop_time": "2020-02-03",
+            "demographic_distribution": [{"age": "25-34", "gender": "male", "percentage": percentage}],
+            "impressions": {"lower_bound": "1000", "upper_bound": "4999"},
+            "currency": "USD",
+            "publisher_platforms": ["facebook"]
+        })
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(year, month, day)
+    }
+
+    #[test]
+    fn month_slices_roll_over_into_january() {
+        assert_eq!(date_slices(date(2019, 11, 15), date(2020, 2, 10), &DateSlice::MONTH), vec![
+            (date(2019, 11, 15), date(2019, 11, 30)),
+            (date(2019, 12, 1), date(2019, 12, 31)),
+            (date(2020, 1, 1), date(2020, 1, 31)),
+            (date(2020, 2, 1), date(2020, 2, 10)),
+        ]);
+    }
+
+    #[test]
+    fn month_slices_end_on_leap_days() {
+        assert_eq!(date_slices(date(2020, 2, 1), date(2020, 3, 1), &DateSlice::MONTH), vec![
+            (date(2020, 2, 1), date(2020, 2, 29)),
+            (date(2020, 3, 1), date(2020, 3, 1)),
+        ]);
+    }
+
+    #[test]
+    fn week_slices_end_on_sunday_when_starting_mid_week() {
+        // 2020-01-01 is a Wednesday
+        assert_eq!(date_slices(date(2020, 1, 1), date(2020, 1, 20), &DateSlice::WEEK), vec![
+            (date(2020, 1, 1), date(2020, 1, 5)),
+            (date(2020, 1, 6), date(2020, 1, 12)),
+            (date(2020, 1, 13), date(2020, 1, 19)),
+            (date(2020, 1, 20), date(2020, 1, 20)),
+        ]);
+    }
+
+    #[test]
+    fn week_slices_roll_over_into_january() {
+        // 2019-12-30 is a Monday ... the week runs into the new year
+        assert_eq!(date_slices(date(2019, 12, 29), date(2020, 1, 6), &DateSlice::WEEK), vec![
+            (date(2019, 12, 29), date(2019, 12, 29)),
+            (date(2019, 12, 30), date(2020, 1, 5)),
+            (date(2020, 1, 6), date(2020, 1, 6)),
+        ]);
+    }
+
+    #[test]
+    fn time_frames_shorter_than_a_slice_give_one_slice() {
+        assert_eq!(date_slices(date(2020, 3, 10), date(2020, 3, 12), &DateSlice::MONTH), vec![(date(2020, 3, 10), date(2020, 3, 12))]);
+        assert_eq!(date_slices(date(2020, 3, 10), date(2020, 3, 12), &DateSlice::WEEK), vec![(date(2020, 3, 10), date(2020, 3, 12))]);
+        assert_eq!(date_slices(date(2020, 3, 10), date(2020, 3, 10), &DateSlice::WEEK), vec![(date(2020, 3, 10), date(2020, 3, 10))]);
+        assert_eq!(date_slices(date(2019, 1, 1), date(2020, 12, 31), &DateSlice::WHOLE), vec![(date(2019, 1, 1), date(2020, 12, 31))]);
+        // An end before the start leaves nothing to collect
+        assert!(date_slices(date(2020, 3, 10), date(2020, 3, 9), &DateSlice::MONTH).is_empty());
+    }
+
+    #[test]
+    fn parses_bare_dates_as_midnight_utc() {
+        assert_eq!(parse_api_date("2020-03-28"), Ok(Utc.ymd(2020, 3, 28).and_hms(0, 0, 0)));
+        assert_eq!(parse_api_date(" 2020-03-28 "), Ok(Utc.ymd(2020, 3, 28).and_hms(0, 0, 0)));
+    }
+
+    #[test]
+    fn parses_timestamps_with_offsets() {
+        assert_eq!(parse_api_date("2020-02-03T15:30:00+0000"), Ok(Utc.ymd(2020, 2, 3).and_hms(15, 30, 0)));
+        assert_eq!(parse_api_date("2020-02-03T15:30:00-0500"), Ok(Utc.ymd(2020, 2, 3).and_hms(20, 30, 0)));
+        assert_eq!(parse_api_date("2020-02-03T15:30:00Z"), Ok(Utc.ymd(2020, 2, 3).and_hms(15, 30, 0)));
+        assert_eq!(parse_api_date("2020-02-03T15:30:00+01:00"), Ok(Utc.ymd(2020, 2, 3).and_hms(14, 30, 0)));
+    }
+
+    #[test]
+    fn rejects_unrecognized_dates() {
+        for date in ["", "garbage", "2020-13-01", "2020-02-30", "28/03/2020", "2020-03-28T25:00:00+0000"].iter() {
+            assert!(parse_api_date(date).is_err(), "{:?} was accepted", date);
+        }
+    }
+
+    #[test]
+    fn parses_well_formed_ads() {
+        let record = parse_ad(ad("0.25"), "US").unwrap();
+        assert_eq!(record.id, "2000000000000023");
+        assert_eq!(record.country.as_deref(), Some("US"));
+        assert_eq!(record.impressions, (1000.0, 4999.0));
+        assert_eq!(record.demographic_distribution.get("male/25-34"), Some(&0.25));
+        assert_eq!(record.ad_delivery_stop_time, Some(Utc.ymd(2020, 2, 3).and_hms(0, 0, 0)));
+    }
+
+    #[test]
+    fn rejects_malformed_ads_instead_of_failing() {
+        let rejected = parse_ad(ad("n/a"), "US").unwrap_err();
+        assert_eq!(rejected.id.as_deref(), Some("2000000000000023"));
+        assert_eq!(rejected.country, "US");
+        assert_eq!(rejected.reason, "Invalid number for demographic_distribution: \"n/a\"");
+        assert_eq!(rejected.ad, ad("n/a"));
+
+        for percentage in ["NaN", "inf", "-inf"].iter() {
+            assert!(parse_ad(ad(percentage), "US").is_err(), "{} was accepted", percentage);
+        }
+
+        let mut bad_date = ad("0.25");
+        bad_date["ad_delivery_start_time"] = json!("soon");
+        assert!(parse_ad(bad_date, "US").unwrap_err().reason.contains("soon"));
+
+        // Ads that aren't objects or lack an id are rejected without one
+        let rejected = parse_ad(json!("not an ad"), "US").unwrap_err();
+        assert_eq!(rejected.id, None);
+    }
+}
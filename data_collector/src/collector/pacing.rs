@@ -0,0 +1,216 @@
+use rand::Rng;
+
+use reqwest::header::HeaderMap;
+
+use serde_json::Value;
+
+use std::time::Duration;
+
+// Headers the Graph API uses to report how much of a quota has been used
+const APP_USAGE_HEADER: &str = "x-app-usage";
+const BUSINESS_USAGE_HEADER: &str = "x-business-use-case-usage";
+
+// Quota usage reported alongside a response
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Usage {
+    // Highest percentage used of any quota (call count, total time, or cpu time)
+    pub percent: f64,
+    // Minutes until a throttled quota can be used again
+    pub regain_access_minutes: u64,
+}
+
+fn header_json(headers: &HeaderMap, name: &str) -> Option<Value> {
+    let value = headers.get(name)?.to_str().ok()?;
+    serde_json::from_str(value).ok()
+}
+
+impl Usage {
+    pub fn from_headers(headers: &HeaderMap) -> Usage {
+        let mut usage = Usage::default();
+        // x-app-usage: {"call_count":28,"total_time":25,"total_cputime":25}
+        if let Some(app_usage) = header_json(headers, APP_USAGE_HEADER) {
+            usage.add_counts(&app_usage);
+        }
+        // x-business-use-case-usage: {"<business id>":[{"type":"ads_archive","call_count":28,...,"estimated_time_to_regain_access":0}]}
+        if let Some(Value::Object(businesses)) = header_json(headers, BUSINESS_USAGE_HEADER) {
+            for use_case in businesses.values().filter_map(Value::as_array).flatten() {
+                usage.add_counts(use_case);
+                if let Some(minutes) = use_case.get("estimated_time_to_regain_access").and_then(Value::as_u64) {
+                    usage.regain_access_minutes = std::cmp::max(usage.regain_access_minutes, minutes);
+                }
+            }
+        }
+        usage
+    }
+
+    fn add_counts(&mut self, counts: &Value) {
+        for key in ["call_count", "total_time", "total_cputime"].iter() {
+            if let Some(percent) = counts.get(key).and_then(Value::as_f64) {
+                self.percent = self.percent.max(percent);
+            }
+        }
+    }
+}
+
+fn backoff_delay(base: Duration, attempt: usize, minimum: Duration) -> Duration {
+    let delay = base * 2u32.saturating_pow(attempt.saturating_sub(1) as u32);
+    // Randomize within [delay / 2, delay] so concurrent collectors don't retry in lockstep
+    let jittered = delay.mul_f64(rand::thread_rng().gen_range(0.5, 1.0));
+    std::cmp::max(jittered, minimum)
+}
+
+// Spaces out requests to stay within the API's quotas and keeps track of time spent waiting
+pub struct Pacer {
+    // Usage percentage at which requests start being slowed down
+    pub usage_threshold: f64,
+    // Pause between requests once usage reaches 100%
+    pub max_pause: Duration,
+    pub waited: Duration,
+    // Replayed responses come from disk ... waits are reported but not slept through
+    pub skip_waits: bool,
+}
+
+impl Pacer {
+    // Slows down as usage approaches a quota instead of waiting to be rate limited
+    pub async fn pace(&mut self, usage: Usage) {
+        if let Some((pause, reason)) = self.pause(usage) {
+            self.wait(pause, &reason).await;
+        }
+    }
+
+    // Pause to take before the next request along with the reason for it ... None while usage is below the threshold
+    fn pause(&self, usage: Usage) -> Option<(Duration, String)> {
+        if usage.regain_access_minutes > 0 {
+            Some((Duration::from_secs(usage.regain_access_minutes * 60), String::from("Quota exhausted")))
+        } else if usage.percent >= self.usage_threshold {
+            // Pause grows linearly from nothing at the threshold to max_pause at 100%
+            let fraction = ((usage.percent - self.usage_threshold) / (100.0 - self.usage_threshold).max(1.0)).min(1.0);
+            Some((self.max_pause.mul_f64(fraction), format!("Quota {:.0}% used", usage.percent)))
+        } else {
+            None
+        }
+    }
+
+    // Jittered exponential backoff ... attempt starts at 1 and the wait never drops below minimum
+    pub async fn backoff(&mut self, base: Duration, attempt: usize, minimum: Duration, reason: &str) {
+        self.wait(backoff_delay(base, attempt, minimum), reason).await;
+    }
+
+    async fn wait(&mut self, duration: Duration, reason: &str) {
+        if duration == Duration::from_secs(0) {
+            return;
+        }
+        if self.skip_waits {
+            println!("{} ... skipping {:.1} second wait while replaying", reason, duration.as_secs_f64());
+            return;
+        }
+        println!("{} ... waiting {:.1} seconds", reason, duration.as_secs_f64());
+        tokio::time::delay_for(duration).await;
+        self.waited += duration;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs.iter() {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn pacer(usage_threshold: f64) -> Pacer {
+        Pacer { usage_threshold, max_pause: Duration::from_secs(100), waited: Duration::from_secs(0), skip_waits: true }
+    }
+
+    #[test]
+    fn usage_takes_highest_percentage_of_every_quota() {
+        let usage = Usage::from_headers(&headers(&[
+            (APP_USAGE_HEADER, r#"{"call_count":28,"total_time":41,"total_cputime":25}"#),
+            (BUSINESS_USAGE_HEADER, r#"{"123":[{"type":"ads_archive","call_count":60,"total_cputime":20,"total_time":30,"estimated_time_to_regain_access":0}],"456":[{"type":"ads_archive","call_count":5,"total_cputime":72,"total_time":1,"estimated_time_to_regain_access":7}]}"#),
+        ]));
+        assert_eq!(usage.percent, 72.0);
+        assert_eq!(usage.regain_access_minutes, 7);
+    }
+
+    #[test]
+    fn usage_reads_app_usage_alone() {
+        let usage = Usage::from_headers(&headers(&[(APP_USAGE_HEADER, r#"{"call_count":85,"total_time":40,"total_cputime":35}"#)]));
+        assert_eq!(usage.percent, 85.0);
+        assert_eq!(usage.regain_access_minutes, 0);
+    }
+
+    #[test]
+    fn usage_ignores_missing_and_malformed_headers() {
+        let usage = Usage::from_headers(&HeaderMap::new());
+        assert_eq!(usage.percent, 0.0);
+        assert_eq!(usage.regain_access_minutes, 0);
+
+        let usage = Usage::from_headers(&headers(&[(APP_USAGE_HEADER, "not json"), (BUSINESS_USAGE_HEADER, r#"{"123":"not a list"}"#)]));
+        assert_eq!(usage.percent, 0.0);
+        assert_eq!(usage.regain_access_minutes, 0);
+    }
+
+    #[test]
+    fn pause_grows_from_threshold_to_max_pause() {
+        let pacer = pacer(80.0);
+        assert!(pacer.pause(Usage { percent: 79.9, regain_access_minutes: 0 }).is_none());
+        assert_eq!(pacer.pause(Usage { percent: 80.0, regain_access_minutes: 0 }).unwrap().0, Duration::from_secs(0));
+        assert_eq!(pacer.pause(Usage { percent: 90.0, regain_access_minutes: 0 }).unwrap().0, Duration::from_secs(50));
+        assert_eq!(pacer.pause(Usage { percent: 100.0, regain_access_minutes: 0 }).unwrap().0, Duration::from_secs(100));
+        // Usage can be reported above 100% ... the pause stays at max_pause
+        assert_eq!(pacer.pause(Usage { percent: 150.0, regain_access_minutes: 0 }).unwrap().0, Duration::from_secs(100));
+    }
+
+    #[test]
+    fn pause_handles_threshold_of_100() {
+        let pacer = pacer(100.0);
+        assert!(pacer.pause(Usage { percent: 99.0, regain_access_minutes: 0 }).is_none());
+        assert_eq!(pacer.pause(Usage { percent: 100.0, regain_access_minutes: 0 }).unwrap().0, Duration::from_secs(0));
+    }
+
+    #[test]
+    fn pause_waits_out_throttled_quota() {
+        let pacer = pacer(80.0);
+        let (pause, reason) = pacer.pause(Usage { percent: 10.0, regain_access_minutes: 3 }).unwrap();
+        assert_eq!(pause, Duration::from_secs(180));
+        assert_eq!(reason, "Quota exhausted");
+    }
+
+    #[test]
+    fn backoff_doubles_within_jitter_bounds() {
+        let base = Duration::from_secs(2);
+        for attempt in 1..=6 {
+            let delay = base * 2u32.pow(attempt as u32 - 1);
+            for _ in 0..50 {
+                let wait = backoff_delay(base, attempt, Duration::from_secs(0));
+                assert!(wait >= delay / 2 && wait <= delay, "attempt {}: {:?} outside {:?}", attempt, wait, delay);
+            }
+        }
+        // Attempt 0 is treated as the first attempt
+        assert!(backoff_delay(base, 0, Duration::from_secs(0)) <= base);
+    }
+
+    #[test]
+    fn backoff_never_drops_below_minimum() {
+        for _ in 0..50 {
+            assert!(backoff_delay(Duration::from_secs(1), 1, Duration::from_secs(30)) >= Duration::from_secs(30));
+        }
+        assert_eq!(backoff_delay(Duration::from_secs(0), 3, Duration::from_secs(5)), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn skipped_waits_are_not_slept_or_counted() {
+        let mut pacer = pacer(80.0);
+        let started = std::time::Instant::now();
+        pacer.pace(Usage { percent: 100.0, regain_access_minutes: 60 }).await;
+        pacer.backoff(Duration::from_secs(60), 5, Duration::from_secs(60), "Rate limited").await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(pacer.waited, Duration::from_secs(0));
+    }
+}
@@ -0,0 +1,655 @@
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+use serde_json::Value;
+
+use std::collections::{HashMap, BTreeMap, BTreeSet};
+use std::str::FromStr;
+use std::f64;
+use std::fs;
+use std::fs::{File, DirBuilder};
+use std::path::{Path, PathBuf};
+use std::io::Write;
+
+use super::{ApiAdData, ApiCountRange, parse_api_date};
+
+// Names of the files that make up a collected dataset
+pub const RECORDS_FILE: &str = "ad_records.json";
+pub const DATA_FILE: &str = "ad_data.json";
+pub const REJECTS_FILE: &str = "rejects.json";
+
+// Single ad as returned by the Ad Library ... kept so aggregated views can be rebuilt without double counting
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdRecord {
+    // Ad Library archive id
+    pub id: String,
+    pub page_id: Option<String>,
+    // Name of the page that ran the ad and who paid for it (the "Paid for by" disclaimer)
+    #[serde(default)]
+    pub page_name: Option<String>,
+    #[serde(default)]
+    pub funding_entity: Option<String>,
+    // Country the ad was collected for ... region names are only unique within a country
+    #[serde(default)]
+    pub country: Option<String>,
+    // Every country the ad was returned for ... the ad is only counted under country, but its regions can lie in any of these
+    #[serde(default)]
+    pub reached_countries: BTreeSet<String>,
+    pub ad_creative_body: Option<String>,
+    // Text of the link attached to the ad ... many ads carry their message here instead of the body
+    #[serde(default)]
+    pub ad_creative_link_title: Option<String>,
+    #[serde(default)]
+    pub ad_creative_link_description: Option<String>,
+    // Usually the domain the link points to
+    #[serde(default)]
+    pub ad_creative_link_caption: Option<String>,
+    // Body, link title, and link description combined ... used as the ad's text during analysis
+    #[serde(default)]
+    pub ad_text: Option<String>,
+    // Languages the ad is written in (eg. "en")
+    #[serde(default)]
+    pub languages: Vec<String>,
+    pub ad_delivery_start_time: DateTime<Utc>,
+    pub ad_delivery_stop_time: Option<DateTime<Utc>>,
+    // Raw impression count (lower and upper bound)
+    pub impressions: (f64, f64),
+    // Impressions fell in the open-ended bucket (eg. ">1M") ... upper bound is unknown and is stored equal to the lower bound
+    #[serde(default)]
+    pub impressions_open_ended: bool,
+    // Amount spent on the ad (lower and upper bound) in the given currency
+    #[serde(default)]
+    pub spend: (f64, f64),
+    #[serde(default)]
+    pub currency: Option<String>,
+    // Maps demographic (gender and age) to the fraction of impressions it received
+    pub demographic_distribution: HashMap<String, f64>,
+    // Maps region to the fraction of impressions it received
+    pub region_distribution: HashMap<String, f64>,
+    // Platforms the ad ran on (eg. facebook, instagram, messenger, audience_network)
+    #[serde(default)]
+    pub publisher_platforms: BTreeSet<String>,
+    // Fields returned by the API that the collector doesn't model, exactly as returned
+    #[serde(default)]
+    pub extra_fields: BTreeMap<String, Value>,
+    // When the copy of the ad was received ... None for records saved before it was kept
+    #[serde(default)]
+    pub collected_at: Option<DateTime<Utc>>,
+}
+
+// Ad that could not be turned into an AdRecord ... kept along with the reason so it can be inspected later
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RejectedAd {
+    pub id: Option<String>,
+    pub country: String,
+    pub reason: String,
+    // Ad exactly as the API returned it
+    pub ad: Value,
+}
+
+pub(super) fn parse_number(value: &str, field: &str) -> Result<f64, String> {
+    match f64::from_str(value.trim()) {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(format!("Invalid number for {}: {:?}", field, value)),
+    }
+}
+
+// Joins the non-empty text fields of an ad with blank lines, skipping repeated text
+fn combine_text(parts: &[&Option<String>]) -> Option<String> {
+    let mut texts: Vec<&str> = Vec::new();
+    for part in parts.iter() {
+        if let Some(text) = part.as_deref().map(str::trim) {
+            if !text.is_empty() && !texts.contains(&text) {
+                texts.push(text);
+            }
+        }
+    }
+    if texts.is_empty() {
+        None
+    } else {
+        Some(texts.join("\n\n"))
+    }
+}
+
+// How the unknown upper bound of open-ended impression ranges is filled in during aggregation
+#[derive(Debug, Clone, Copy, Default)]
+pub enum OpenRange {
+    // Upper bound equals the lower bound ... understates the largest ads
+    #[default]
+    Lower,
+    // Upper bound is the given number of impressions (or the lower bound if that is higher)
+    Cap(f64),
+    // Upper bound is the lower bound multiplied by the given factor
+    Scale(f64),
+}
+
+// Parses "lower", "cap:<impressions>", or "scale:<factor>"
+impl FromStr for OpenRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<OpenRange, String> {
+        let value = value.trim().to_lowercase();
+        let (strategy, argument) = match value.find(':') {
+            Some(idx) => (&value[..idx], Some(&value[idx + 1..])),
+            None => (value.as_str(), None),
+        };
+        let argument = argument.map(|argument| parse_number(argument, "open range")).transpose()?;
+        match (strategy, argument) {
+            ("lower", None) => Ok(OpenRange::Lower),
+            ("cap", Some(cap)) => Ok(OpenRange::Cap(cap)),
+            ("scale", Some(factor)) if factor >= 1.0 => Ok(OpenRange::Scale(factor)),
+            _ => Err(format!("Invalid open range strategy {:?} ... expected lower, cap:<impressions>, or scale:<factor of at least 1>", value)),
+        }
+    }
+}
+
+impl OpenRange {
+    fn upper_bound(&self, lower_bound: f64) -> f64 {
+        match self {
+            OpenRange::Lower => lower_bound,
+            OpenRange::Cap(cap) => cap.max(lower_bound),
+            OpenRange::Scale(factor) => lower_bound * factor,
+        }
+    }
+}
+
+// Missing lower bound is treated as zero and missing upper bound as equal to the lower bound
+fn parse_count_range(range: &ApiCountRange, field: &str) -> Result<(f64, f64), String> {
+    let lower_bound = match &range.lower_bound {
+        Some(lower_bound) => parse_number(lower_bound, field)?,
+        None => 0.0,
+    };
+    let upper_bound = match &range.upper_bound {
+        Some(upper_bound) => parse_number(upper_bound, field)?,
+        None => lower_bound,
+    };
+    Ok((lower_bound, upper_bound))
+}
+
+impl AdRecord {
+    // Fails with a description of the first value that could not be parsed
+    pub(super) fn from_api(ad: &ApiAdData, country: &str) -> Result<AdRecord, String> {
+        let impressions = match &ad.impressions {
+            Some(impressions) => parse_count_range(impressions, "impressions")?,
+            None => (0.0, 0.0),
+        };
+        let spend = match &ad.spend {
+            Some(spend) => parse_count_range(spend, "spend")?,
+            None => (0.0, 0.0),
+        };
+
+        let mut demographic_distribution: HashMap<String, f64> = HashMap::new();
+        if let Some(demographics) = ad.demographic_distribution.as_ref() {
+            for demographic in demographics.iter() {
+                // In order to later serialize the resulting HashMap, the demographic_key needs to be a String
+                let demographic_key = demographic.gender.clone() + "/" + demographic.age.as_str();
+                let demographic_percentage = parse_number(&demographic.percentage, "demographic_distribution")?;
+                *demographic_distribution.entry(demographic_key).or_insert(0.0) += demographic_percentage;
+            }
+        }
+        let mut region_distribution: HashMap<String, f64> = HashMap::new();
+        if let Some(regions) = ad.region_distribution.as_ref() {
+            for region in regions.iter() {
+                let region_percentage = parse_number(&region.percentage, "region_distribution")?;
+                *region_distribution.entry(region.region.clone()).or_insert(0.0) += region_percentage;
+            }
+        }
+
+        let ad_delivery_start_time = match &ad.ad_delivery_start_time {
+            Some(start_time) => parse_api_date(start_time).map_err(|e| format!("Invalid ad_delivery_start_time: {}", e))?,
+            None => return Err("Missing ad_delivery_start_time".to_string()),
+        };
+        let ad_delivery_stop_time = match &ad.ad_delivery_stop_time {
+            Some(stop_time) => Some(parse_api_date(stop_time).map_err(|e| format!("Invalid ad_delivery_stop_time: {}", e))?),
+            None => None,
+        };
+
+        Ok(AdRecord {
+            id: ad.id.clone(),
+            page_id: ad.page_id.clone(),
+            page_name: ad.page_name.clone(),
+            funding_entity: ad.funding_entity.clone(),
+            country: Some(country.to_string()),
+            reached_countries: std::iter::once(country.to_string()).collect(),
+            ad_creative_body: ad.ad_creative_body.clone(),
+            ad_creative_link_title: ad.ad_creative_link_title.clone(),
+            ad_creative_link_description: ad.ad_creative_link_description.clone(),
+            ad_creative_link_caption: ad.ad_creative_link_caption.clone(),
+            ad_text: combine_text(&[&ad.ad_creative_body, &ad.ad_creative_link_title, &ad.ad_creative_link_description]),
+            languages: ad.languages.clone().unwrap_or_default(),
+            ad_delivery_start_time,
+            ad_delivery_stop_time,
+            impressions,
+            impressions_open_ended: ad.impressions.as_ref().is_some_and(|range| range.lower_bound.is_some() && range.upper_bound.is_none()),
+            spend,
+            currency: ad.currency.clone(),
+            demographic_distribution,
+            region_distribution,
+            publisher_platforms: ad.publisher_platforms.iter().flatten().map(|platform| platform.to_lowercase()).collect(),
+            extra_fields: ad.extra_fields.clone(),
+            collected_at: None,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AdMetric {
+    // Body, link title, and link description of the creative combined (see AdRecord::ad_text)
+    //   Ads sharing a body can differ in their links ... the first text in sort order is kept so it doesn't depend on ad order.
+    #[serde(default)]
+    pub ad_text: Option<String>,
+    // Maps demographic (gender and age) to raw impression count (lower and upper bound)
+    pub demographic_impression: HashMap<String, (f64, f64)>,
+    // Maps region to raw impression count (lower and upper bound)
+    pub region_impression: HashMap<String, (f64, f64)>,
+    // Total amount spent on the ad (lower and upper bound)
+    #[serde(default)]
+    pub spend: (f64, f64),
+    // Currency spend is reported in ... taken from the first ad seen with a currency
+    #[serde(default)]
+    pub currency: Option<String>,
+    // Spend is allocated to demographics and regions in proportion to the impressions they received
+    #[serde(default)]
+    pub demographic_spend: HashMap<String, (f64, f64)>,
+    #[serde(default)]
+    pub region_spend: HashMap<String, (f64, f64)>,
+    // Countries the ad was collected for
+    #[serde(default)]
+    pub countries: BTreeSet<String>,
+    // Pages that ran the ad ... keyword searches return ads from any advertiser
+    #[serde(default)]
+    pub page_ids: BTreeSet<String>,
+    #[serde(default)]
+    pub page_names: BTreeSet<String>,
+    // Entities that paid for the ad
+    #[serde(default)]
+    pub funding_entities: BTreeSet<String>,
+    #[serde(default)]
+    pub languages: BTreeSet<String>,
+    // Maps platform to the raw impression count (lower and upper bound) of ads that ran on it
+    //   The API doesn't split impressions by platform so an ad's full count is added to every platform it ran on.
+    #[serde(default)]
+    pub platform_impression: HashMap<String, (f64, f64)>,
+    // Maps platform to the number of ads that ran on it
+    #[serde(default)]
+    pub platform_ads: HashMap<String, usize>,
+    // Number of ads that ran this creative
+    #[serde(default)]
+    pub ad_count: usize,
+    // Earliest delivery start and latest delivery stop of the ads that ran this creative
+    #[serde(default)]
+    pub first_delivery: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_delivery: Option<DateTime<Utc>>,
+    // Whether any of the ads had not stopped being delivered when they were collected (including ads scheduled to stop later)
+    #[serde(default)]
+    pub active: bool,
+    // Days between the first and last delivery ... active creatives are counted through the time the ads were collected
+    #[serde(default)]
+    pub active_days: i64,
+    // Number of ads whose impressions fell in the open-ended bucket ... their upper bounds are estimated and
+    // impression figures of the creative are censored when this is above zero
+    #[serde(default)]
+    pub open_ended_ads: usize,
+    #[serde(default)]
+    pub impressions_open_ended: bool,
+}
+
+impl AdMetric {
+    // Adds the figures of another entry ... ads present in both are double counted
+    pub fn add(&mut self, other: AdMetric) {
+        let delivered_through = std::cmp::max(self.delivered_through(), other.delivered_through());
+        add_breakdown(&mut self.demographic_impression, &other.demographic_impression);
+        add_breakdown(&mut self.region_impression, &other.region_impression);
+        add_breakdown(&mut self.demographic_spend, &other.demographic_spend);
+        add_breakdown(&mut self.region_spend, &other.region_spend);
+        self.spend = (self.spend.0 + other.spend.0, self.spend.1 + other.spend.1);
+        if self.currency.is_none() {
+            self.currency = other.currency;
+        }
+        self.add_ad_text(other.ad_text);
+        self.countries.extend(other.countries);
+        self.page_ids.extend(other.page_ids);
+        self.page_names.extend(other.page_names);
+        self.funding_entities.extend(other.funding_entities);
+        self.languages.extend(other.languages);
+        add_breakdown(&mut self.platform_impression, &other.platform_impression);
+        for (platform, count) in other.platform_ads.into_iter() {
+            *self.platform_ads.entry(platform).or_insert(0) += count;
+        }
+        self.ad_count += other.ad_count;
+        self.first_delivery = match (self.first_delivery, other.first_delivery) {
+            (Some(first1), Some(first2)) => Some(std::cmp::min(first1, first2)),
+            (first1, first2) => first1.or(first2),
+        };
+        self.last_delivery = std::cmp::max(self.last_delivery, other.last_delivery);
+        self.active = self.active || other.active;
+        self.open_ended_ads += other.open_ended_ads;
+        self.impressions_open_ended = self.impressions_open_ended || other.impressions_open_ended;
+        if let (Some(first_delivery), Some(delivered_through)) = (self.first_delivery, delivered_through) {
+            self.active_days = std::cmp::max((delivered_through - first_delivery).num_days(), 0);
+        }
+    }
+
+    fn add_ad_text(&mut self, ad_text: Option<String>) {
+        let keep_current = match (&self.ad_text, &ad_text) {
+            (Some(current), Some(other)) => current <= other,
+            (current, _) => current.is_some(),
+        };
+        if !keep_current {
+            self.ad_text = ad_text;
+        }
+    }
+
+    // Time the ads were last known to be delivered ... the collection time for active creatives
+    fn delivered_through(&self) -> Option<DateTime<Utc>> {
+        self.first_delivery.map(|first_delivery| first_delivery + chrono::Duration::days(self.active_days))
+    }
+
+    fn update_active_days(&mut self, as_of: DateTime<Utc>) {
+        if let Some(first_delivery) = self.first_delivery {
+            let last_delivery = if self.active { as_of } else { self.last_delivery.unwrap_or(first_delivery) };
+            self.active_days = std::cmp::max((last_delivery - first_delivery).num_days(), 0);
+        }
+    }
+}
+
+// Adds an ad's share of a count (eg. impressions, spend) to a breakdown
+fn add_share(breakdown: &mut HashMap<String, (f64, f64)>, distribution: &HashMap<String, f64>, count: (f64, f64)) {
+    for (key, percentage) in distribution.iter() {
+        let (prev_lower, prev_upper) = breakdown.get(key).cloned().unwrap_or((0.0, 0.0));
+        breakdown.insert(
+            key.clone(),
+            (prev_lower + count.0 * percentage, prev_upper + count.1 * percentage)
+        );
+    }
+}
+
+// Sums two breakdowns together
+fn add_breakdown(breakdown: &mut HashMap<String, (f64, f64)>, other: &HashMap<String, (f64, f64)>) {
+    for (key, count) in other.iter() {
+        let (prev_lower, prev_upper) = breakdown.get(key).cloned().unwrap_or((0.0, 0.0));
+        breakdown.insert(key.clone(), (prev_lower + count.0, prev_upper + count.1));
+    }
+}
+
+// Time a set of records was collected ... the newest copy's collection time
+//   Records saved before collection times were kept fall back to the latest delivery time they hold.
+pub fn collected_as_of(records: &HashMap<String, AdRecord>) -> DateTime<Utc> {
+    records.values()
+           .filter_map(|record| record.collected_at)
+           .max()
+           .or_else(|| {
+               records.values()
+                      .flat_map(|record| std::iter::once(record.ad_delivery_start_time).chain(record.ad_delivery_stop_time))
+                      .max()
+           })
+           .unwrap_or_else(Utc::now)
+}
+
+// Builds the view used for analysis ... ads sharing the same text are combined into one metric
+//   When the records span several countries, region keys are prefixed with the country (eg. "GB/Wales")
+//   so regions with the same name in different countries are kept apart. Ads that reached several countries
+//   keep bare region keys as their regions can't be told apart by country. Whether ads are active is judged as of
+//   the given time (see collected_as_of) rather than now, so stats don't drift as the dataset ages.
+pub fn aggregate(records: &HashMap<String, AdRecord>, open_range: OpenRange, as_of: DateTime<Utc>) -> HashMap<String, AdMetric> {
+    let countries: BTreeSet<&String> = records.values().filter_map(|record| record.country.as_ref()).collect();
+    let qualify_regions = countries.len() > 1;
+
+    let mut res: HashMap<String, AdMetric> = HashMap::new();
+    for record in records.values() {
+        // Entries are keyed by body so they line up with datasets collected before link text was kept ... ads
+        // without a body fall back to their combined text and ads with no text at all are ignored
+        let key = match record.ad_creative_body.as_ref().or(record.ad_text.as_ref()) {
+            Some(key) => key,
+            None => continue,
+        };
+
+        let metric = res.entry(key.clone()).or_default();
+        metric.add_ad_text(record.ad_text.clone().or_else(|| record.ad_creative_body.clone()));
+        let region_distribution: HashMap<String, f64> = match (&record.country, qualify_regions) {
+            (Some(country), true) if record.reached_countries.len() <= 1 => {
+                record.region_distribution.iter().map(|(region, percentage)| (format!("{}/{}", country, region), *percentage)).collect()
+            },
+            _ => {
+                record.region_distribution.clone()
+            },
+        };
+        let impressions = if record.impressions_open_ended {
+            metric.open_ended_ads += 1;
+            metric.impressions_open_ended = true;
+            (record.impressions.0, open_range.upper_bound(record.impressions.0))
+        } else {
+            record.impressions
+        };
+        add_share(&mut metric.demographic_impression, &record.demographic_distribution, impressions);
+        add_share(&mut metric.region_impression, &region_distribution, impressions);
+        add_share(&mut metric.demographic_spend, &record.demographic_distribution, record.spend);
+        add_share(&mut metric.region_spend, &region_distribution, record.spend);
+        if let Some(country) = &record.country {
+            metric.countries.insert(country.clone());
+        }
+        if let Some(page_id) = &record.page_id {
+            metric.page_ids.insert(page_id.clone());
+        }
+        if let Some(page_name) = &record.page_name {
+            metric.page_names.insert(page_name.clone());
+        }
+        if let Some(funding_entity) = &record.funding_entity {
+            metric.funding_entities.insert(funding_entity.clone());
+        }
+        metric.languages.extend(record.languages.iter().cloned());
+        let platforms: HashMap<String, f64> = record.publisher_platforms.iter().map(|platform| (platform.clone(), 1.0)).collect();
+        add_share(&mut metric.platform_impression, &platforms, impressions);
+        for platform in record.publisher_platforms.iter() {
+            *metric.platform_ads.entry(platform.clone()).or_insert(0) += 1;
+        }
+        metric.ad_count += 1;
+        metric.first_delivery = Some(match metric.first_delivery {
+            Some(first_delivery) => std::cmp::min(first_delivery, record.ad_delivery_start_time),
+            None => record.ad_delivery_start_time,
+        });
+        match record.ad_delivery_stop_time {
+            // Ads scheduled to stop after they were collected were still running ... their stop time hasn't happened yet
+            Some(stop_time) if stop_time > as_of => {
+                metric.active = true;
+                metric.last_delivery = std::cmp::max(metric.last_delivery, Some(as_of));
+            },
+            Some(stop_time) => {
+                metric.last_delivery = std::cmp::max(metric.last_delivery, Some(stop_time));
+            },
+            None => {
+                metric.active = true;
+            },
+        }
+        metric.spend = (metric.spend.0 + record.spend.0, metric.spend.1 + record.spend.1);
+        if metric.currency.is_none() {
+            metric.currency = record.currency.clone();
+        }
+    }
+
+    for metric in res.values_mut() {
+        metric.update_active_days(as_of);
+    }
+    res
+}
+
+pub(super) fn read_json_file<T: serde::de::DeserializeOwned>(path: &Path) -> std::io::Result<T> {
+    let content = fs::read_to_string(path)?;
+    serde_json::from_str(&content).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+// Reads the aggregated view of a dataset directory
+pub fn load_data(path_dir: &str) -> std::io::Result<HashMap<String, AdMetric>> {
+    read_json_file(&PathBuf::from(path_dir).join(DATA_FILE))
+}
+
+// Reads the per-ad records of a dataset directory
+pub fn load_records(path_dir: &str) -> std::io::Result<HashMap<String, AdRecord>> {
+    read_json_file(&PathBuf::from(path_dir).join(RECORDS_FILE))
+}
+
+// Reads ad_data.json given either its dataset directory or the file itself
+fn load_data_at(path: &str) -> std::io::Result<HashMap<String, AdMetric>> {
+    if Path::new(path).is_file() {
+        read_json_file(Path::new(path))
+    } else {
+        load_data(path)
+    }
+}
+
+// Merge two collected datasets
+//   Ads are matched by archive id so ads present in both datasets are only counted once. Datasets
+//   collected before per-ad records were kept only have ad_data.json ... these are merged by summing
+//   metrics, which double counts ads present in both datasets. Either dataset can also be given as an
+//   ad_data.json file (as merge used to take), in which case target_path is the file to write to.
+pub fn merge_results(path1: &str, path2: &str, target_path: &str, open_range: OpenRange) -> std::io::Result<()> {
+    let records1 = PathBuf::from(path1).join(RECORDS_FILE);
+    let records2 = PathBuf::from(path2).join(RECORDS_FILE);
+    if records1.exists() && records2.exists() {
+        let mut res = load_records(path1)?;
+        // Later copy of an ad replaces the earlier one as it holds the most recent impression counts
+        res.extend(load_records(path2)?);
+        return save_results(&res, target_path, open_range);
+    }
+
+    println!("Merging datasets without {} ... ads present in both datasets will be double counted", RECORDS_FILE);
+    let doc1: HashMap<String, AdMetric> = load_data_at(path1)?;
+    let doc2: HashMap<String, AdMetric> = load_data_at(path2)?;
+    let mut res: HashMap<String, AdMetric> = doc1;
+
+    for (ad_message, doc2_metrics) in doc2.into_iter() {
+        match res.get_mut(&ad_message) {
+            Some(res_metrics) => {
+                // Ad was in doc1 ... combine
+                res_metrics.add(doc2_metrics);
+            },
+            None => {
+                res.insert(ad_message, doc2_metrics);
+            },
+        }
+    }
+
+    let target_file = if Path::new(path1).is_file() || Path::new(path2).is_file() {
+        PathBuf::from(target_path)
+    } else {
+        PathBuf::from(target_path).join(DATA_FILE)
+    };
+    // Create directory if it does not exist
+    if let Some(target_dir) = target_file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        DirBuilder::new().recursive(true).create(target_dir)?;
+    }
+    let mut output = File::create(target_file)?;
+    output.write_all(serde_json::to_string(&res).unwrap().as_bytes())?;
+
+    Ok(())
+}
+
+// Saves results from Collector::collect() into path_dir
+//   Per-ad records are written to ad_records.json and the aggregated view to ad_data.json
+pub fn save_results(records: &HashMap<String, AdRecord>, path_dir: &str, open_range: OpenRange) -> std::io::Result<()> {
+    // Create directory if it does not exist
+    DirBuilder::new().recursive(true).create(path_dir)?;
+
+    let mut file = File::create(PathBuf::from(path_dir).join(RECORDS_FILE))?;
+    file.write_all(serde_json::to_string(records).unwrap().as_bytes())?;
+
+    let mut file = File::create(PathBuf::from(path_dir).join(DATA_FILE))?;
+    file.write_all(serde_json::to_string(&aggregate(records, open_range, collected_as_of(records))).unwrap().as_bytes())?;
+    Ok(())
+}
+
+// Saves ads that could not be parsed into rejects.json in path_dir
+pub fn save_rejects(rejects: &[RejectedAd], path_dir: &str) -> std::io::Result<()> {
+    // Create directory if it does not exist
+    DirBuilder::new().recursive(true).create(path_dir)?;
+
+    let mut file = File::create(PathBuf::from(path_dir).join(REJECTS_FILE))?;
+    file.write_all(serde_json::to_string_pretty(rejects).unwrap().as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn record(id: &str, body: Option<&str>, link_title: Option<&str>) -> AdRecord {
+        let mut record: AdRecord = serde_json::from_value(json!({
+            "id": id,
+            "ad_creative_body": body,
+            "ad_creative_link_title": link_title,
+            "ad_delivery_start_time": "2020-02-01T00:00:00Z",
+            "ad_delivery_stop_time": "2020-02-02T00:00:00Z",
+            "impressions": [1000.0, 1999.0],
+            "demographic_distribution": {},
+            "region_distribution": {}
+        })).unwrap();
+        record.ad_text = combine_text(&[&record.ad_creative_body, &record.ad_creative_link_title]);
+        record
+    }
+
+    fn aggregate_records(records: Vec<AdRecord>) -> HashMap<String, AdMetric> {
+        let records: HashMap<String, AdRecord> = records.into_iter().map(|record| (record.id.clone(), record)).collect();
+        aggregate(&records, OpenRange::default(), Utc::now())
+    }
+
+    #[test]
+    fn aggregates_by_body_and_keeps_combined_text() {
+        let data = aggregate_records(vec![
+            record("1", Some("Vote early"), Some("Find your polling place")),
+            record("2", Some("Vote early"), Some("Check your registration")),
+            record("3", None, Some("Request your ballot")),
+            record("4", None, None),
+        ]);
+        assert_eq!(data.len(), 2);
+
+        // Ads sharing a body are combined even when their links differ
+        let metric = &data["Vote early"];
+        assert_eq!(metric.ad_count, 2);
+        assert_eq!(metric.ad_text.as_deref(), Some("Vote early\n\nCheck your registration"));
+
+        // Ads without a body are keyed by their combined text
+        let metric = &data["Request your ballot"];
+        assert_eq!(metric.ad_count, 1);
+        assert_eq!(metric.ad_text.as_deref(), Some("Request your ballot"));
+    }
+
+    #[test]
+    fn prefixes_regions_of_single_country_ads_only() {
+        let mut us_ad = record("1", Some("Vote early"), None);
+        us_ad.country = Some(String::from("US"));
+        us_ad.reached_countries = vec![String::from("US")].into_iter().collect();
+        us_ad.region_distribution.insert(String::from("Texas"), 1.0);
+        let mut both_ad = record("2", Some("Vote early"), None);
+        both_ad.country = Some(String::from("US"));
+        both_ad.reached_countries = vec![String::from("GB"), String::from("US")].into_iter().collect();
+        both_ad.region_distribution.insert(String::from("England"), 1.0);
+        let mut gb_ad = record("3", Some("Vote early"), None);
+        gb_ad.country = Some(String::from("GB"));
+        gb_ad.region_distribution.insert(String::from("Wales"), 1.0);
+
+        let data = aggregate_records(vec![us_ad, both_ad, gb_ad]);
+        let mut regions: Vec<&String> = data["Vote early"].region_impression.keys().collect();
+        regions.sort();
+        assert_eq!(regions, ["England", "GB/Wales", "US/Texas"]);
+    }
+
+    #[test]
+    fn parses_numbers() {
+        assert_eq!(parse_number("0.25", "percentage"), Ok(0.25));
+        assert_eq!(parse_number(" 1000 ", "impressions"), Ok(1000.0));
+        assert_eq!(parse_number("-3", "spend"), Ok(-3.0));
+        assert_eq!(parse_number("1e3", "spend"), Ok(1000.0));
+    }
+
+    #[test]
+    fn rejects_garbage_and_non_finite_numbers() {
+        for value in ["", "n/a", "1,000", "12abc", "NaN", "nan", "inf", "-inf", "infinity", "1e400"].iter() {
+            assert!(parse_number(value, "spend").is_err(), "{:?} was accepted", value);
+        }
+        assert_eq!(parse_number("n/a", "percentage"), Err(String::from("Invalid number for percentage: \"n/a\"")));
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::fs::{File, DirBuilder};
+use std::path::{Path, PathBuf};
+use std::io::Write;
+
+use super::records::{AdMetric, parse_number, read_json_file, load_data};
+
+// Name of the file an imported report is saved to
+//   Kept apart from ad_data.json as report entries are pages rather than ad texts and must not reach the NLP scripts
+pub const REPORT_DATA_FILE: &str = "report_data.json";
+
+// Columns of the advertiser CSV in an Ad Library Report download
+//   eg. "Page ID","Page name","Disclaimer","Amount spent (USD)","Number of ads in Library"
+struct ReportColumns {
+    page_id: Option<usize>,
+    page_name: usize,
+    disclaimer: usize,
+    spend: usize,
+    ad_count: usize,
+    // Taken from the spend column header (eg. USD)
+    currency: Option<String>,
+}
+
+impl ReportColumns {
+    fn from_headers(headers: &csv::StringRecord) -> Result<ReportColumns, String> {
+        // Header names are matched loosely as they differ slightly between downloads
+        let names: Vec<String> = headers.iter().map(|name| name.trim_start_matches('\u{feff}').trim().to_lowercase()).collect();
+        let find = |prefix: &str| names.iter().position(|name| name.starts_with(prefix));
+        let require = |prefix: &str| find(prefix).ok_or(format!("Report has no \"{}\" column", prefix));
+
+        let spend = require("amount spent")?;
+        let currency = names[spend].split('(')
+                                   .nth(1)
+                                   .map(|currency| currency.trim_end_matches(')').trim().to_uppercase())
+                                   .filter(|currency| !currency.is_empty());
+        Ok(ReportColumns {
+            page_id: find("page id"),
+            page_name: require("page name")?,
+            disclaimer: require("disclaimer")?,
+            spend,
+            ad_count: require("number of ads")?,
+            currency,
+        })
+    }
+}
+
+// Spend is reported exactly except for small advertisers, which fall in a bucket (eg. "≤100")
+fn parse_report_spend(value: &str) -> Result<(f64, f64), String> {
+    let value = value.trim().replace(',', "");
+    match value.strip_prefix('≤').or_else(|| value.strip_prefix("<=")) {
+        Some(upper) => Ok((0.0, parse_number(upper, "spend")?)),
+        None => {
+            let spend = parse_number(&value, "spend")?;
+            Ok((spend, spend))
+        },
+    }
+}
+
+// Reads an Ad Library Report advertiser CSV into the aggregated format of ad_data.json (saved as report_data.json)
+//   The report holds a row per page and disclaimer with no ad text, demographics, or regions, so every row
+//   becomes an entry named after its page and disclaimer that only holds spend and the number of ads.
+pub fn import_report(path: &Path, country: &str) -> Result<HashMap<String, AdMetric>, String> {
+    let mut reader = csv::Reader::from_path(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let headers = reader.headers().map_err(|e| format!("Failed to read {}: {}", path.display(), e))?.clone();
+    let columns = ReportColumns::from_headers(&headers)?;
+
+    let mut data: HashMap<String, AdMetric> = HashMap::new();
+    for (row, record) in reader.records().enumerate() {
+        // Header is the first line of the file
+        let line = row + 2;
+        let record = record.map_err(|e| format!("Line {}: {}", line, e))?;
+        let field = |column: usize| record.get(column).map(str::trim).unwrap_or("");
+
+        let page_name = field(columns.page_name);
+        let disclaimer = field(columns.disclaimer);
+        let page_id = columns.page_id.map(field).filter(|page_id| !page_id.is_empty());
+        let spend = parse_report_spend(field(columns.spend)).map_err(|e| format!("Line {}: {}", line, e))?;
+        let ad_count = field(columns.ad_count).replace(',', "")
+                                              .parse::<usize>()
+                                              .map_err(|_| format!("Line {}: Invalid number of ads: {:?}", line, field(columns.ad_count)))?;
+
+        let name = match (page_name.is_empty(), disclaimer.is_empty()) {
+            (false, false) => format!("{} (paid for by {})", page_name, disclaimer),
+            (false, true) => page_name.to_string(),
+            (true, _) => page_id.unwrap_or("Unknown").to_string(),
+        };
+        // Rows that share a page name and disclaimer (eg. pages with the same name) are added together
+        let metric = data.entry(name).or_default();
+        metric.spend = (metric.spend.0 + spend.0, metric.spend.1 + spend.1);
+        metric.currency = columns.currency.clone();
+        metric.ad_count += ad_count;
+        metric.countries.insert(country.to_string());
+        if let Some(page_id) = page_id {
+            metric.page_ids.insert(page_id.to_string());
+        }
+        if !page_name.is_empty() {
+            metric.page_names.insert(page_name.to_string());
+        }
+        if !disclaimer.is_empty() {
+            metric.funding_entities.insert(disclaimer.to_string());
+        }
+    }
+    Ok(data)
+}
+
+// Saves an imported report into report_data.json in path_dir ... there are no per-ad records to save
+pub fn save_report(data: &HashMap<String, AdMetric>, path_dir: &str) -> std::io::Result<()> {
+    // Create directory if it does not exist
+    DirBuilder::new().recursive(true).create(path_dir)?;
+
+    let mut file = File::create(PathBuf::from(path_dir).join(REPORT_DATA_FILE))?;
+    file.write_all(serde_json::to_string(data).unwrap().as_bytes())?;
+    Ok(())
+}
+
+// Reads the aggregated view of a dataset ... either ad_data.json of a collection or report_data.json of an imported report
+pub fn load_summary(path_dir: &str) -> std::io::Result<HashMap<String, AdMetric>> {
+    let report_path = PathBuf::from(path_dir).join(REPORT_DATA_FILE);
+    if report_path.exists() {
+        return read_json_file(&report_path);
+    }
+    load_data(path_dir)
+}
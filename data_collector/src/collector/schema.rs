@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+use std::fs::{File, DirBuilder};
+use std::path::PathBuf;
+use std::io::Write;
+
+// Name of the file the schema drift report of a collection is saved to
+pub const SCHEMA_DRIFT_FILE: &str = "schema_drift.json";
+
+// Every ad field the collector understands along with the JSON type it expects
+//   Nested fields are written as parent.child and array elements as parent[]. Null is accepted for any field.
+const EXPECTED_AD_FIELDS: &[(&str, &str)] = &[
+    ("id", "string"),
+    ("page_id", "string"),
+    ("page_name", "string"),
+    ("funding_entity", "string"),
+    ("ad_creative_body", "string"),
+    ("ad_creative_link_title", "string"),
+    ("ad_creative_link_description", "string"),
+    ("ad_creative_link_caption", "string"),
+    ("languages", "array"),
+    ("languages[]", "string"),
+    ("ad_delivery_start_time", "string"),
+    ("ad_delivery_stop_time", "string"),
+    ("demographic_distribution", "array"),
+    ("demographic_distribution[]", "object"),
+    ("demographic_distribution[].age", "string"),
+    ("demographic_distribution[].gender", "string"),
+    ("demographic_distribution[].percentage", "string"),
+    ("impressions", "object"),
+    ("impressions.lower_bound", "string"),
+    ("impressions.upper_bound", "string"),
+    ("region_distribution", "array"),
+    ("region_distribution[]", "object"),
+    ("region_distribution[].region", "string"),
+    ("region_distribution[].percentage", "string"),
+    ("spend", "object"),
+    ("spend.lower_bound", "string"),
+    ("spend.upper_bound", "string"),
+    ("currency", "string"),
+    ("publisher_platforms", "array"),
+    ("publisher_platforms[]", "string"),
+];
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn expected_type(path: &str) -> Option<&'static str> {
+    EXPECTED_AD_FIELDS.iter().find(|(field, _)| *field == path).map(|(_, json_type)| *json_type)
+}
+
+// Field names and JSON types seen in the responses of a collection
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SchemaObservations {
+    pub ads: usize,
+    // Maps each ad field to the number of times it was seen with each JSON type
+    pub ad_fields: BTreeMap<String, BTreeMap<String, usize>>,
+    // Maps each top level response field other than data and paging to the number of responses it was seen in
+    pub response_fields: BTreeMap<String, usize>,
+}
+
+impl SchemaObservations {
+    pub fn observe_response(&mut self, extra_fields: &serde_json::Map<String, Value>) {
+        for field in extra_fields.keys() {
+            *self.response_fields.entry(field.clone()).or_default() += 1;
+        }
+    }
+
+    pub fn observe_ad(&mut self, ad: &Value) {
+        self.ads += 1;
+        if let Value::Object(fields) = ad {
+            for (field, value) in fields.iter() {
+                self.observe_field(field, value);
+            }
+        }
+    }
+
+    // Only fields the collector understands are descended into ... unknown fields are reported as a whole
+    fn observe_field(&mut self, path: &str, value: &Value) {
+        *self.ad_fields.entry(path.to_string()).or_default().entry(json_type(value).to_string()).or_default() += 1;
+        if expected_type(path).is_none() {
+            return;
+        }
+        match value {
+            Value::Array(elements) => {
+                let element_path = format!("{}[]", path);
+                for element in elements.iter() {
+                    self.observe_field(&element_path, element);
+                }
+            },
+            Value::Object(fields) => {
+                for (field, value) in fields.iter() {
+                    self.observe_field(&format!("{}.{}", path, field), value);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Compares what was seen with what the collector expects for the fields that were requested
+    pub fn drift(&self, requested_fields: &[String]) -> SchemaDrift {
+        let mut drift = SchemaDrift { ads: self.ads, ..SchemaDrift::default() };
+        for (field, types) in self.ad_fields.iter() {
+            match expected_type(field) {
+                None => {
+                    drift.new_fields.push(FieldDrift { field: field.clone(), expected: None, seen: types.clone() });
+                },
+                Some(expected) => {
+                    if types.keys().any(|seen| seen != expected && seen != "null") {
+                        drift.changed_fields.push(FieldDrift { field: field.clone(), expected: Some(expected.to_string()), seen: types.clone() });
+                    }
+                },
+            }
+        }
+        // Fields the API may leave out of an ad (eg. stop time of running ads) are only missing if no ad had them
+        if self.ads > 0 {
+            drift.missing_fields = requested_fields.iter()
+                                                   .filter(|field| expected_type(field).is_some() && !self.ad_fields.contains_key(*field))
+                                                   .cloned()
+                                                   .collect();
+        }
+        drift.new_response_fields = self.response_fields.keys().cloned().collect();
+        drift
+    }
+}
+
+// Field whose name or type the collector doesn't expect
+#[derive(Debug, Serialize)]
+pub struct FieldDrift {
+    pub field: String,
+    // Type the collector expects ... None for fields it doesn't know
+    pub expected: Option<String>,
+    // Number of times the field was seen with each JSON type
+    pub seen: BTreeMap<String, usize>,
+}
+
+// Differences between the responses of a collection and what the collector expects
+#[derive(Debug, Default, Serialize)]
+pub struct SchemaDrift {
+    pub ads: usize,
+    // Ad fields the collector doesn't model ... kept in the extra_fields of each record
+    pub new_fields: Vec<FieldDrift>,
+    // Requested ad fields that no ad came with
+    pub missing_fields: Vec<String>,
+    // Ad fields seen with a type other than the one the collector expects ... ads holding them are rejected
+    pub changed_fields: Vec<FieldDrift>,
+    // Top level response fields other than data and paging
+    pub new_response_fields: Vec<String>,
+}
+
+impl SchemaDrift {
+    pub fn is_empty(&self) -> bool {
+        self.new_fields.is_empty() && self.missing_fields.is_empty() && self.changed_fields.is_empty() && self.new_response_fields.is_empty()
+    }
+}
+
+// Saves the schema drift report into schema_drift.json in path_dir
+pub fn save_schema_drift(drift: &SchemaDrift, path_dir: &str) -> std::io::Result<()> {
+    // Create directory if it does not exist
+    DirBuilder::new().recursive(true).create(path_dir)?;
+
+    let mut file = File::create(PathBuf::from(path_dir).join(SCHEMA_DRIFT_FILE))?;
+    file.write_all(serde_json::to_string_pretty(drift).unwrap().as_bytes())?;
+    Ok(())
+}
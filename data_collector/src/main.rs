@@ -9,38 +9,222 @@ extern crate askama;
 
 mod collector;
 mod web_server;
+mod mock_server;
 
-use collector::{Collector, AdStatus, merge_results, save_results};
+use collector::{Collector, AdStatus, AdType, DateSlice, OpenRange, AdRecord, Collection, PartialCollection, SchemaObservations, collected_as_of, merge_results, save_results, save_rejects, save_schema_drift, load_records, load_checkpoint, strip_access_token, CHECKPOINT_FILE, DEFAULT_API_VERSION, DEFAULT_FIELDS, REQUIRED_FIELDS, RECORDS_FILE, REJECTS_FILE, SCHEMA_DRIFT_FILE, Cassette, Recorder, Replayer, reaggregate, rotate_archive, import_report, save_report, ARCHIVE_FILE};
 use web_server::launch_web_server;
+use mock_server::launch_mock_server;
 
 use chrono::{DateTime, NaiveDateTime, Utc, NaiveDate, NaiveTime};
 
 use clap::{Arg, App, SubCommand, ArgMatches};
 
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Saves how the responses differed from what the collector expects and lists the differences
+fn report_schema_drift(schema: &SchemaObservations, fields: &[String], save_path: &str) -> Result<(), String> {
+    let drift = schema.drift(fields);
+    save_schema_drift(&drift, save_path).map_err(|_| "Failed to save schema drift report")?;
+    if drift.is_empty() {
+        return Ok(());
+    }
+    println!("The API responses differ from what the collector expects (see {}):", SCHEMA_DRIFT_FILE);
+    for field in drift.new_fields.iter() {
+        println!("  New field: {}", field.field);
+    }
+    for field in drift.missing_fields.iter() {
+        println!("  Missing field: {}", field);
+    }
+    for field in drift.changed_fields.iter() {
+        let seen: Vec<String> = field.seen.iter().map(|(json_type, count)| format!("{} x{}", json_type, count)).collect();
+        println!("  Changed type: {} (expected {}, seen {})", field.field, field.expected.as_deref().unwrap_or("?"), seen.join(", "));
+    }
+    for field in drift.new_response_fields.iter() {
+        println!("  New response field: {}", field);
+    }
+    Ok(())
+}
+
+// Persists whatever was collected ... partial results are saved even when collection stopped early
+fn save_collection(collection: Result<Collection, PartialCollection>, save_path: &str, fields: &[String], open_range: OpenRange) -> Result<(), String> {
+    match collection {
+        Ok(res) => {
+            save_results(&res.records, save_path, open_range).map_err(|_| "Failed to save results")?;
+            save_rejects(&res.rejects, save_path).map_err(|_| "Failed to save rejected ads")?;
+            report_schema_drift(&res.schema, fields, save_path)?;
+            println!("Collection finished: saved {} ads", res.records.len());
+            if !res.rejects.is_empty() {
+                println!("{} ads could not be parsed and were saved to {}", res.rejects.len(), REJECTS_FILE);
+            }
+            Ok(())
+        },
+        Err(partial) => {
+            save_results(&partial.collection.records, save_path, open_range).map_err(|_| "Failed to save partial results")?;
+            save_rejects(&partial.collection.rejects, save_path).map_err(|_| "Failed to save rejected ads")?;
+            report_schema_drift(&partial.collection.schema, fields, save_path)?;
+            println!("Saved {} ads collected before stopping", partial.collection.records.len());
+            println!("Stopped at: {}", partial.endpoint);
+            Err(format!("Collection stopped early ({}). Rerun with --resume to continue.", partial.error))
+        },
+    }
+}
+
+// Name of the environment variable access tokens can be passed through instead of --access_token
+const ACCESS_TOKEN_VAR: &str = "AD_LIBRARY_ACCESS_TOKEN";
+
+// Access tokens come from a file (one per line) followed by those given with --access_token or through the environment
+fn read_access_tokens(matches: &ArgMatches<'_>) -> Result<Vec<String>, String> {
+    let mut access_tokens: Vec<String> = Vec::new();
+    if let Some(path) = matches.value_of("access_token_file") {
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read access token file {}: {}", path, e))?;
+        access_tokens.extend(
+            content.lines()
+                   .map(str::trim)
+                   .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                   .map(String::from)
+        );
+    }
+    if let Some(values) = matches.values_of("access_token") {
+        access_tokens.extend(values.map(str::trim).filter(|token| !token.is_empty()).map(String::from));
+    }
+    if access_tokens.is_empty() {
+        return Err(format!("An access token must be given with --access_token, --access_token_file, or {}", ACCESS_TOKEN_VAR));
+    }
+    Ok(access_tokens)
+}
+
+// API responses are saved to the directory given with --record or served from the one given with --replay
+fn open_cassette(matches: &ArgMatches<'_>) -> Result<Cassette, String> {
+    if let Some(dir) = matches.value_of("record") {
+        let recorder = Recorder::new(Path::new(dir)).map_err(|e| format!("Failed to open recording directory {}: {}", dir, e))?;
+        println!("Recording API responses to {}", dir);
+        return Ok(Cassette::Record(recorder));
+    }
+    if let Some(dir) = matches.value_of("replay") {
+        let replayer = Replayer::load(Path::new(dir)).map_err(|e| format!("Failed to read recording {}: {}", dir, e))?;
+        println!("Replaying {} recorded API responses from {}", replayer.remaining(), dir);
+        return Ok(Cassette::Replay(replayer));
+    }
+    Ok(Cassette::Off)
+}
+
+// Recorded responses that were never requested point to a replay that doesn't match the recorded collection
+fn report_unreplayed(collector: &Collector) {
+    if let Cassette::Replay(replayer) = &collector.cassette {
+        if replayer.remaining() > 0 {
+            println!("{} recorded API responses were not replayed", replayer.remaining());
+        }
+    }
+}
+
 async fn parse_collect_subcommand(matches: &ArgMatches<'_>) -> Result<(), String> {
     // Unwrap all arguments that are either required or have some default value
     let save_path = matches.value_of("save_path").unwrap();
-    let access_token = matches.value_of("access_token").unwrap();
+    // Replayed collections never reach the API ... no access token is needed
+    let access_tokens = match read_access_tokens(matches) {
+        Err(_) if matches.is_present("replay") => vec![String::from("replay")],
+        access_tokens => access_tokens?,
+    };
+    let open_range = matches.value_of("open_impressions").unwrap().parse::<OpenRange>()?;
+    let checkpoint_path = PathBuf::from(save_path).join(CHECKPOINT_FILE);
+    let archive_path = PathBuf::from(save_path).join(ARCHIVE_FILE);
+
+    if matches.is_present("resume") {
+        // Collection parameters come from the checkpoint ... only the access token is taken from the command line
+        let checkpoint = load_checkpoint(&checkpoint_path)
+                            .map_err(|e| format!("Failed to read checkpoint {}: {}", checkpoint_path.display(), e))?;
+        let mut collector = checkpoint.collector;
+        collector.access_tokens = access_tokens;
+        collector.checkpoint_path = Some(checkpoint_path);
+        collector.archive_path = Some(archive_path);
+        collector.cassette = open_cassette(matches)?;
+        println!("Resuming collection with {} ads already collected", checkpoint.results.len());
+
+        let collection = Collection { records: checkpoint.results, rejects: checkpoint.rejects, schema: checkpoint.schema };
+        let collection = collector.resume(checkpoint.queries, collection).await;
+        report_unreplayed(&collector);
+        return save_collection(collection, save_path, &collector.fields, open_range);
+    }
+
     let page_ids = matches.values_of("page_ids")
-                                                                .unwrap()
+                                                                .unwrap_or_default()
                                                                 .map(|id_str| id_str.parse::<u64>());
     if !page_ids.clone().all(|r| r.is_ok()) {
         return Err("Page ids must be 64 bit (unsigned) integers".to_string());
     }
     let page_ids: Vec<u64> = page_ids.map(|r| r.unwrap()).collect();
+    let search_terms = matches.value_of("search_terms").map(String::from);
+    let split_pages = matches.is_present("split_pages");
+    let concurrency = matches.value_of("concurrency").unwrap().parse::<usize>().map_err(|_| "Failed to parse concurrency")?;
+    if concurrency == 0 {
+        return Err("Concurrency must be at least 1".to_string());
+    }
+    let exact_phrase = matches.is_present("exact_phrase");
+
+    // Incremental collections build on the records of an earlier collection
+    let records: HashMap<String, AdRecord> = match matches.value_of("incremental") {
+        Some(dataset) => {
+            let records = load_records(dataset).map_err(|e| format!("Failed to read {}/{}: {}", dataset, RECORDS_FILE, e))?;
+            println!("Collecting incrementally on top of {} ads from {}", records.len(), dataset);
+            records
+        },
+        None => HashMap::new(),
+    };
+    // Ads that had stopped being delivered won't change ... paging can stop once only these are left
+    //   Ads scheduled to stop after they were collected were still running and need their figures refreshed.
+    let as_of = collected_as_of(&records);
+    let finished_ads: HashSet<String> = records.values()
+                                               .filter(|record| {
+                                                   record.ad_delivery_stop_time.is_some_and(|stop_time| stop_time <= record.collected_at.unwrap_or(as_of))
+                                               })
+                                               .map(|record| record.id.clone())
+                                               .collect();
 
-    let year_start = matches.value_of("year_start").unwrap().parse::<i32>().map_err(|_| "Failed to parse year_start")?;
     let month_start = matches.value_of("month_start").unwrap().parse::<u32>().map_err(|_| "Failed to parse month_start")?;
     let day_start = matches.value_of("day_start").unwrap().parse::<u32>().map_err(|_| "Failed to parse day_start")?;
-
-    let year_end = matches.value_of("year_end").unwrap().parse::<i32>().map_err(|_| "Failed to parse year_end")?;
     let month_end = matches.value_of("month_end").unwrap().parse::<u32>().map_err(|_| "Failed to parse month_end")?;
     let day_end = matches.value_of("day_end").unwrap().parse::<u32>().map_err(|_| "Failed to parse day_end")?;
 
     let retries = matches.value_of("retries").unwrap().parse::<usize>().map_err(|_| "Failed to parse retries")?;
     let batch_size = matches.value_of("batch_size").unwrap().parse::<usize>().map_err(|_| "Failed to parse batch_size")?;
-    let endpoint = matches.value_of("endpoint").map(|s| String::from(s));
+    let backoff_secs = matches.value_of("backoff").unwrap().parse::<u64>().map_err(|_| "Failed to parse backoff")?;
+    let retry_delay_secs = matches.value_of("retry_delay").unwrap().parse::<u64>().map_err(|_| "Failed to parse retry_delay")?;
+    let usage_threshold = matches.value_of("usage_threshold").unwrap().parse::<f64>().map_err(|_| "Failed to parse usage_threshold")?;
+    let endpoint = matches.value_of("endpoint").map(strip_access_token);
+    let base_url = matches.value_of("base_url").unwrap();
+
+    // Graph API versions are written as vX.Y ... accept a bare X.Y as well
+    let api_version = matches.value_of("api_version").unwrap().trim();
+    let api_version = if api_version.starts_with('v') { api_version.to_string() } else { format!("v{}", api_version) };
+    let valid_version = api_version[1..].split('.').count() == 2 &&
+                        api_version[1..].split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+    if !valid_version {
+        return Err(format!("Invalid api_version: {}", api_version));
+    }
+
+    // Fields every ad needs are requested even when they were left out
+    let mut fields: Vec<String> = match matches.values_of("fields") {
+        Some(fields) => fields.map(|field| field.trim().to_string()).filter(|field| !field.is_empty()).collect(),
+        None => DEFAULT_FIELDS.iter().map(|field| field.to_string()).collect(),
+    };
+    for field in REQUIRED_FIELDS.iter().rev() {
+        if !fields.iter().any(|f| f == field) {
+            fields.insert(0, field.to_string());
+        }
+    }
     let ad_status = matches.value_of("ad_status").unwrap();
+    let ad_type = matches.value_of("ad_type").unwrap();
+    let date_slice = matches.value_of("date_slice").unwrap();
+    let countries: Vec<String> = matches.values_of("countries")
+                                        .unwrap()
+                                        .map(|country| country.trim().to_uppercase())
+                                        .filter(|country| !country.is_empty())
+                                        .collect();
+    if countries.is_empty() {
+        return Err("At least one country must be given".to_string());
+    }
 
     // Convert ad_status string to enum
     let ad_status: AdStatus = match ad_status.to_uppercase().as_str() {
@@ -50,14 +234,53 @@ async fn parse_collect_subcommand(matches: &ArgMatches<'_>) -> Result<(), String
         _ => {Err("Invalid value for ad_status")}
     }?;
 
+    // Convert ad_type string to enum
+    let ad_type: AdType = match ad_type.to_uppercase().as_str() {
+        "ALL" => {Ok(AdType::ALL)},
+        "POLITICAL_AND_ISSUE_ADS" => {Ok(AdType::POLITICAL_AND_ISSUE_ADS)},
+        "HOUSING_ADS" => {Ok(AdType::HOUSING_ADS)},
+        "EMPLOYMENT_ADS" => {Ok(AdType::EMPLOYMENT_ADS)},
+        "CREDIT_ADS" => {Ok(AdType::CREDIT_ADS)},
+        _ => {Err("Invalid value for ad_type")}
+    }?;
+
+    // Convert date_slice string to enum
+    let date_slice: DateSlice = match date_slice.to_uppercase().as_str() {
+        "WHOLE" => {Ok(DateSlice::WHOLE)},
+        "MONTH" => {Ok(DateSlice::MONTH)},
+        "WEEK" => {Ok(DateSlice::WEEK)},
+        _ => {Err("Invalid value for date_slice")}
+    }?;
+
     // Create date time and validate dates
-    let start_date = NaiveDate::from_ymd_opt(year_start, month_start, day_start)
-                                    .ok_or(format!("Invalid starting date: {}-{}-{}", year_start, month_start, day_start))?;
+    //   Without a starting year, incremental collections start on the day the newest known ad started and
+    //   run through today unless an ending year is given
+    let start_date = match matches.value_of("year_start") {
+        Some(year_start) => {
+            let year_start = year_start.parse::<i32>().map_err(|_| "Failed to parse year_start")?;
+            NaiveDate::from_ymd_opt(year_start, month_start, day_start)
+                        .ok_or(format!("Invalid starting date: {}-{}-{}", year_start, month_start, day_start))?
+        },
+        None => {
+            records.values()
+                   .map(|record| record.ad_delivery_start_time.naive_utc().date())
+                   .max()
+                   .ok_or("year_start must be given as the existing dataset holds no ads")?
+        },
+    };
     let start_time = NaiveTime::from_hms(0, 0, 0);
     let start_date_time = NaiveDateTime::new(start_date, start_time);
 
-    let end_date = NaiveDate::from_ymd_opt(year_end, month_end, day_end)
-                                    .ok_or(format!("Invalid ending date: {}-{}-{}", year_end, month_end, day_end))?;
+    let end_date = match matches.value_of("year_end") {
+        Some(year_end) => {
+            let year_end = year_end.parse::<i32>().map_err(|_| "Failed to parse year_end")?;
+            NaiveDate::from_ymd_opt(year_end, month_end, day_end)
+                        .ok_or(format!("Invalid ending date: {}-{}-{}", year_end, month_end, day_end))?
+        },
+        None => {
+            Utc::now().naive_utc().date()
+        },
+    };
     let end_time = NaiveTime::from_hms(23, 59, 59);
     let end_date_time = NaiveDateTime::new(end_date, end_time);
 
@@ -69,18 +292,41 @@ async fn parse_collect_subcommand(matches: &ArgMatches<'_>) -> Result<(), String
         start_date_time: DateTime::<Utc>::from_utc(start_date_time, Utc),
         end_date_time: DateTime::<Utc>::from_utc(end_date_time, Utc),
         ad_status,
+        ad_type,
+        countries,
         page_ids,
-        access_token: String::from(access_token),
+        search_terms,
+        exact_phrase,
+        access_tokens,
         retries,
         batch_size,
+        backoff_secs,
+        retry_delay_secs,
+        usage_threshold,
+        api_version,
+        fields,
+        base_url: String::from(base_url),
+        split_pages,
+        concurrency,
+        date_slice,
         endpoint,
+        finished_ads,
+        checkpoint_path: Some(checkpoint_path),
+        archive_path: Some(archive_path.clone()),
+        cassette: open_cassette(matches)?,
     };
 
-    // Collect data from the Ad Library API
-    let res = collector.collect().await.map_err(|e| e.to_string())?;
-    save_results(&res, save_path).map_err(|e| e.to_string()).map_err(|_| "Failed to save results")?;
+    // Archive is only appended to by resumed and incremental collections ... a fresh collection must not mix in ads of an earlier one
+    if matches.value_of("incremental").is_none() {
+        if let Some(rotated) = rotate_archive(&archive_path).map_err(|e| format!("Failed to move aside {}: {}", archive_path.display(), e))? {
+            println!("Moved the archive of an earlier collection to {}", rotated.display());
+        }
+    }
 
-    return Ok(());
+    // Collect data from the Ad Library API
+    let collection = collector.collect(records).await;
+    report_unreplayed(&collector);
+    save_collection(collection, save_path, &collector.fields, open_range)
 }
 
 //#[tokio::main]
@@ -97,20 +343,57 @@ async fn main() -> Result<(), String> {
                             )
                             .arg(Arg::with_name("access_token")
                                 .long("access_token")
-                                .required(true)
-                                .help("Access token for Ad Library API")
+                                .required(false)
+                                .help("Access token for Ad Library API. Can be repeated (or comma separated) to rotate to the next token once one expires. Prefer access_token_file or the environment variable as command lines are visible to other users.")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .use_delimiter(true)
+                                .env(ACCESS_TOKEN_VAR)
+                                .hide_env_values(true)
+                            )
+                            .arg(Arg::with_name("access_token_file")
+                                .long("access_token_file")
+                                .required(false)
+                                .help("File holding access tokens for Ad Library API, one per line. These are used before any given with access_token.")
                                 .takes_value(true)
                             )
                             .arg(Arg::with_name("page_ids")
                                 .long("page_ids")
-                                .required(true)
+                                .required_unless_one(&["resume", "search_terms"])
                                 .help("Facebook page ids to grab ads from")
                                 .takes_value(true)
                                 .use_delimiter(true)
                             )
+                            .arg(Arg::with_name("search_terms")
+                                .long("search_terms")
+                                .required(false)
+                                .help("Collect ads from any page that mention these terms. Can be combined with page_ids.")
+                                .takes_value(true)
+                            )
+                            .arg(Arg::with_name("exact_phrase")
+                                .long("exact_phrase")
+                                .required(false)
+                                .help("Match search_terms as an exact phrase instead of as unordered keywords")
+                                .takes_value(false)
+                                .requires("search_terms")
+                            )
+                            .arg(Arg::with_name("split_pages")
+                                .long("split_pages")
+                                .required(false)
+                                .help("Collect each page id separately so pages can be collected concurrently")
+                                .takes_value(false)
+                            )
+                            .arg(Arg::with_name("concurrency")
+                                .long("concurrency")
+                                .required(false)
+                                .help("Maximum number of queries (countries or split pages) collected at the same time")
+                                .takes_value(true)
+                                .default_value("1")
+                            )
                             .arg(Arg::with_name("year_start")
                                 .long("year_start")
-                                .required(true)
+                                .required_unless_one(&["resume", "incremental"])
                                 .help("Starting date's year")
                                 .takes_value(true)
                             )
@@ -130,7 +413,7 @@ async fn main() -> Result<(), String> {
                             )
                             .arg(Arg::with_name("year_end")
                                 .long("year_end")
-                                .required(true)
+                                .required_unless_one(&["resume", "incremental"])
                                 .help("Ending date's year")
                                 .takes_value(true)
                             )
@@ -162,12 +445,81 @@ async fn main() -> Result<(), String> {
                                 .takes_value(true)
                                 .default_value("1000")
                             )
+                            .arg(Arg::with_name("backoff")
+                                .long("backoff")
+                                .required(false)
+                                .help("Seconds to wait before retrying after being rate limited. Doubles with every consecutive retry.")
+                                .takes_value(true)
+                                .default_value("60")
+                            )
+                            .arg(Arg::with_name("retry_delay")
+                                .long("retry_delay")
+                                .required(false)
+                                .help("Seconds to wait before retrying any other failed request. Doubles with every consecutive retry.")
+                                .takes_value(true)
+                                .default_value("2")
+                            )
+                            .arg(Arg::with_name("usage_threshold")
+                                .long("usage_threshold")
+                                .required(false)
+                                .help("Quota usage (percent) reported by the API at which requests start being slowed down")
+                                .takes_value(true)
+                                .default_value("75")
+                            )
                             .arg(Arg::with_name("endpoint")
                                 .long("endpoint")
                                 .required(false)
                                 .help("Custom API endpoint is used for collecting ads. Can be used to resume progress.")
                                 .takes_value(true)
                             )
+                            .arg(Arg::with_name("resume")
+                                .long("resume")
+                                .required(false)
+                                .help("Resume an unfinished collection from the checkpoint in save_path")
+                                .takes_value(false)
+                                .conflicts_with("endpoint")
+                            )
+                            .arg(Arg::with_name("incremental")
+                                .long("incremental")
+                                .required(false)
+                                .help("Directory of an earlier collection. Only ads newer than it holds are collected (along with updated figures for ads that are still running) and merged into its records.")
+                                .takes_value(true)
+                                .conflicts_with("resume")
+                            )
+                            .arg(Arg::with_name("record")
+                                .long("record")
+                                .required(false)
+                                .help("Directory to save every raw API response to, in the order they were received.")
+                                .takes_value(true)
+                            )
+                            .arg(Arg::with_name("replay")
+                                .long("replay")
+                                .required(false)
+                                .help("Directory of responses saved with --record. They are served in place of the API so nothing is requested over the network.")
+                                .takes_value(true)
+                                .conflicts_with("record")
+                            )
+                            .arg(Arg::with_name("base_url")
+                                .long("base_url")
+                                .required(false)
+                                .help("Scheme and host of the Graph API. Point this at a mock Ad Library to collect offline.")
+                                .takes_value(true)
+                                .default_value("https://graph.facebook.com")
+                            )
+                            .arg(Arg::with_name("api_version")
+                                .long("api_version")
+                                .required(false)
+                                .help("Graph API version to query (eg. v5.0). Change this when a version is sunset.")
+                                .takes_value(true)
+                                .default_value(DEFAULT_API_VERSION)
+                            )
+                            .arg(Arg::with_name("fields")
+                                .long("fields")
+                                .required(false)
+                                .help("Comma separated list of ad fields to request. id and ad_delivery_start_time are always requested. Defaults to every field the collector understands.")
+                                .takes_value(true)
+                                .use_delimiter(true)
+                            )
                             .arg(Arg::with_name("ad_status")
                                 .long("ad_status")
                                 .required(false)
@@ -177,20 +529,103 @@ async fn main() -> Result<(), String> {
                                 .possible_values(&["ALL", "ACTIVE", "INACTIVE"])
                                 .case_insensitive(true)
                             )
+                            .arg(Arg::with_name("ad_type")
+                                .long("ad_type")
+                                .required(false)
+                                .help("Type of ads to collect")
+                                .takes_value(true)
+                                .default_value("POLITICAL_AND_ISSUE_ADS")
+                                .possible_values(&["ALL", "POLITICAL_AND_ISSUE_ADS", "HOUSING_ADS", "EMPLOYMENT_ADS", "CREDIT_ADS"])
+                                .case_insensitive(true)
+                            )
+                            .arg(Arg::with_name("countries")
+                                .long("countries")
+                                .required(false)
+                                .help("ISO country codes of the countries ads reached (eg. US,GB,DE). Each country is collected separately.")
+                                .takes_value(true)
+                                .use_delimiter(true)
+                                .default_value("US")
+                            )
+                            .arg(Arg::with_name("open_impressions")
+                                .long("open_impressions")
+                                .required(false)
+                                .help("How to estimate the upper bound of open-ended impression ranges (eg. >1M): lower, cap:<impressions>, or scale:<factor>")
+                                .takes_value(true)
+                                .default_value("lower")
+                            )
+                            .arg(Arg::with_name("date_slice")
+                                .long("date_slice")
+                                .required(false)
+                                .help("Split the time frame into WEEK or MONTH long slices that are collected separately")
+                                .takes_value(true)
+                                .default_value("WHOLE")
+                                .possible_values(&["WHOLE", "MONTH", "WEEK"])
+                                .case_insensitive(true)
+                            )
                         )
                         .subcommand(SubCommand::with_name("merge")
-                            .about("Merges two jsons files that were generated during collection")
+                            .about("Merges two datasets that were generated during collection")
                             .arg(Arg::with_name("path1")
                                 .required(true)
                                 .takes_value(true)
+                                .help("Directory holding the first dataset, or its ad_data.json file")
                             )
                             .arg(Arg::with_name("path2")
                                 .required(true)
                                 .takes_value(true)
+                                .help("Directory holding the second dataset, or its ad_data.json file")
+                            )
+                            .arg(Arg::with_name("target")
+                                .required(true)
+                                .takes_value(true)
+                                .help("Directory to save the merged dataset. When merging ad_data.json files, the file to save the merged ad_data.json to.")
+                            )
+                            .arg(Arg::with_name("open_impressions")
+                                .long("open_impressions")
+                                .required(false)
+                                .help("How to estimate the upper bound of open-ended impression ranges (eg. >1M): lower, cap:<impressions>, or scale:<factor>")
+                                .takes_value(true)
+                                .default_value("lower")
+                            )
+                        )
+                        .subcommand(SubCommand::with_name("reaggregate")
+                            .about("Rebuilds a dataset from the raw ads archived during collection without calling the API")
+                            .arg(Arg::with_name("path")
+                                .required(true)
+                                .takes_value(true)
+                                .help("Directory holding the archive (raw_ads.ndjson) of a collection")
+                            )
+                            .arg(Arg::with_name("target")
+                                .required(false)
+                                .takes_value(true)
+                                .help("Directory to save the rebuilt dataset. Defaults to the directory holding the archive.")
+                            )
+                            .arg(Arg::with_name("open_impressions")
+                                .long("open_impressions")
+                                .required(false)
+                                .help("How to estimate the upper bound of open-ended impression ranges (eg. >1M): lower, cap:<impressions>, or scale:<factor>")
+                                .takes_value(true)
+                                .default_value("lower")
+                            )
+                        )
+                        .subcommand(SubCommand::with_name("import-report")
+                            .about("Builds a dataset from the advertiser CSV of an Ad Library Report download, for when there is no API access")
+                            .arg(Arg::with_name("report")
+                                .required(true)
+                                .takes_value(true)
+                                .help("Advertiser CSV of the report (eg. FacebookAdLibraryReport_2020-10-01_US_last_90_days_advertisers.csv)")
                             )
                             .arg(Arg::with_name("target")
                                 .required(true)
                                 .takes_value(true)
+                                .help("Directory to save the dataset")
+                            )
+                            .arg(Arg::with_name("country")
+                                .long("country")
+                                .required(false)
+                                .help("Country the report was downloaded for")
+                                .takes_value(true)
+                                .default_value("US")
                             )
                         )
                         .subcommand(SubCommand::with_name("launch")
@@ -201,20 +636,58 @@ async fn main() -> Result<(), String> {
                                 .help("Address(IP:PORT) to bind web server")
                             )
                         )
+                        .subcommand(SubCommand::with_name("mock")
+                            .about("Launches a mock Ad Library API that serves canned responses")
+                            .arg(Arg::with_name("address")
+                                .required(true)
+                                .takes_value(true)
+                                .help("Address(IP:PORT) to bind mock server")
+                            )
+                            .arg(Arg::with_name("fixture_dir")
+                                .required(false)
+                                .takes_value(true)
+                                .default_value("mock/ad_library")
+                                .help("Directory holding canned response pages (0.json, 1.json, ...)")
+                            )
+                        )
         .get_matches();
 
     if let Some(matches) = matches.subcommand_matches("collect") {
         parse_collect_subcommand(matches).await?;
     } else if let Some(matches) = matches.subcommand_matches("merge") {
-        // Merge two resulting files together ... can be used to consolidate data collection done over many days
+        // Merge two resulting datasets together ... can be used to consolidate data collection done over many days
         merge_results(
             matches.value_of("path1").unwrap(),
             matches.value_of("path2").unwrap(),
-            matches.value_of("target").unwrap()
-        ).map_err(|_| "Failed to merge files")?;
+            matches.value_of("target").unwrap(),
+            matches.value_of("open_impressions").unwrap().parse::<OpenRange>()?
+        ).map_err(|_| "Failed to merge datasets")?;
+    } else if let Some(matches) = matches.subcommand_matches("reaggregate") {
+        // Raw ads are parsed and aggregated again ... picks up changes to the parsing and aggregation rules
+        let path = matches.value_of("path").unwrap();
+        let target = matches.value_of("target").unwrap_or(path);
+        let archive_path = PathBuf::from(path).join(ARCHIVE_FILE);
+        let collection = reaggregate(&archive_path).map_err(|e| format!("Failed to read archive {}: {}", archive_path.display(), e))?;
+        let open_range = matches.value_of("open_impressions").unwrap().parse::<OpenRange>()?;
+        save_results(&collection.records, target, open_range).map_err(|_| "Failed to save results")?;
+        save_rejects(&collection.rejects, target).map_err(|_| "Failed to save rejected ads")?;
+        println!("Rebuilt {} ads from {}", collection.records.len(), archive_path.display());
+        if !collection.rejects.is_empty() {
+            println!("{} ads could not be parsed and were saved to {}", collection.rejects.len(), REJECTS_FILE);
+        }
+    } else if let Some(matches) = matches.subcommand_matches("import-report") {
+        // Reports only break spend and ad counts down by page ... the dataset has no per-ad records or demographics
+        let report = PathBuf::from(matches.value_of("report").unwrap());
+        let target = matches.value_of("target").unwrap();
+        let country = matches.value_of("country").unwrap().trim().to_uppercase();
+        let data = import_report(&report, &country)?;
+        save_report(&data, target).map_err(|_| "Failed to save imported report")?;
+        println!("Imported {} pages from {}", data.len(), report.display());
     } else if let Some(matches) = matches.subcommand_matches("launch") {
         launch_web_server(matches.value_of("address").unwrap()).await?;
+    } else if let Some(matches) = matches.subcommand_matches("mock") {
+        launch_mock_server(matches.value_of("address").unwrap(), matches.value_of("fixture_dir").unwrap()).await?;
     }
 
-    return Ok(());
+    Ok(())
 }
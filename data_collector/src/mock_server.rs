@@ -0,0 +1,189 @@
+use actix_web::{web, App, HttpResponse, HttpServer, Responder, HttpRequest};
+use actix_web::http::StatusCode;
+
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Canned responses served by the mock Ad Library
+//   Pages are read from files named 0.json, 1.json, ... in the fixture directory. A page file holds
+//   an ApiResponse body without the paging cursor which is filled in by the server. A file named
+//   N.error.json holds a Graph API error body that is served the first time page N is requested
+//   so the collector's retry logic can be exercised. A file named N.headers.json holds a map of
+//   headers (eg. x-app-usage) added to every response for page N. Ads not delivered between
+//   ad_delivery_date_min and ad_delivery_date_max are left out of the pages. A file named tokens.json
+//   maps access tokens to the number of requests they can make before expiring ... tokens that are not
+//   listed never expire.
+struct MockAdLibrary {
+    pages: Vec<Value>,
+    errors: HashMap<usize, Value>,
+    headers: HashMap<usize, HashMap<String, String>>,
+    token_limits: HashMap<String, usize>,
+    // Number of times each page has been requested
+    hits: Mutex<HashMap<usize, usize>>,
+    // Number of requests made with each access token
+    token_hits: Mutex<HashMap<String, usize>>,
+}
+
+fn read_json(path: &Path) -> Result<Value, String> {
+    let content = fs::read_to_string(path).map_err(|_| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).map_err(|_| format!("Failed to parse {}", path.display()))
+}
+
+fn load_fixtures(fixture_dir: &str) -> Result<MockAdLibrary, String> {
+    let dir = PathBuf::from(fixture_dir);
+    let mut pages: Vec<Value> = Vec::new();
+    loop {
+        let page_path = dir.join(format!("{}.json", pages.len()));
+        if !page_path.exists() {
+            break;
+        }
+        pages.push(read_json(&page_path)?);
+    }
+    if pages.is_empty() {
+        return Err(format!("No pages found in {}", fixture_dir));
+    }
+
+    let mut errors: HashMap<usize, Value> = HashMap::new();
+    let mut headers: HashMap<usize, HashMap<String, String>> = HashMap::new();
+    for page in 0..pages.len() {
+        let error_path = dir.join(format!("{}.error.json", page));
+        if error_path.exists() {
+            errors.insert(page, read_json(&error_path)?);
+        }
+        let headers_path = dir.join(format!("{}.headers.json", page));
+        if headers_path.exists() {
+            let page_headers = serde_json::from_value(read_json(&headers_path)?)
+                                    .map_err(|_| format!("Headers in {} must be strings", headers_path.display()))?;
+            headers.insert(page, page_headers);
+        }
+    }
+
+    let tokens_path = dir.join("tokens.json");
+    let token_limits = if tokens_path.exists() {
+        serde_json::from_value(read_json(&tokens_path)?)
+            .map_err(|_| format!("{} must map access tokens to request counts", tokens_path.display()))?
+    } else {
+        HashMap::new()
+    };
+
+    Ok(MockAdLibrary {
+        pages,
+        errors,
+        headers,
+        token_limits,
+        hits: Mutex::new(HashMap::new()),
+        token_hits: Mutex::new(HashMap::new()),
+    })
+}
+
+// Whether an ad was delivered on any day between date_min and date_max ... ads with no stop time are still running
+//   Dates are compared as YYYY-MM-DD strings which sort chronologically.
+fn delivered_within(ad: &Value, date_min: Option<&String>, date_max: Option<&String>) -> bool {
+    let date = |field: &str| ad.get(field).and_then(Value::as_str).map(|time| time.get(..10).unwrap_or(time));
+    if let (Some(date_max), Some(start)) = (date_max, date("ad_delivery_start_time")) {
+        if start > date_max.as_str() {
+            return false;
+        }
+    }
+    if let (Some(date_min), Some(stop)) = (date_min, date("ad_delivery_stop_time")) {
+        if stop < date_min.as_str() {
+            return false;
+        }
+    }
+    true
+}
+
+// Serves the page selected by the `after` cursor ... mirrors the shape of graph.facebook.com/{version}/ads_archive
+async fn ads_archive(req: HttpRequest, state: web::Data<MockAdLibrary>, query: web::Query<HashMap<String, String>>) -> impl Responder {
+    let page = match query.get("after") {
+        None => 0,
+        Some(cursor) => {
+            match cursor.parse::<usize>() {
+                Ok(page) if page < state.pages.len() => page,
+                _ => {
+                    return HttpResponse::BadRequest().content_type("application/json").body(
+                        r#"{"error":{"message":"Invalid cursor","type":"OAuthException","code":100}}"#
+                    );
+                },
+            }
+        },
+    };
+
+    if let Some(access_token) = query.get("access_token") {
+        if let Some(limit) = state.token_limits.get(access_token) {
+            let mut token_hits = state.token_hits.lock().unwrap();
+            let count = token_hits.entry(access_token.clone()).or_insert(0);
+            *count += 1;
+            if *count > *limit {
+                return HttpResponse::BadRequest().content_type("application/json").body(
+                    r#"{"error":{"message":"Error validating access token: Session has expired","type":"OAuthException","code":190,"error_subcode":463}}"#
+                );
+            }
+        }
+    }
+
+    let hits = {
+        let mut hits = state.hits.lock().unwrap();
+        let count = hits.entry(page).or_insert(0);
+        *count += 1;
+        *count
+    };
+    let mut response = HttpResponse::Ok();
+    if let Some(page_headers) = state.headers.get(&page) {
+        for (name, value) in page_headers.iter() {
+            response.header(name.as_str(), value.as_str());
+        }
+    }
+    if hits == 1 {
+        if let Some(error) = state.errors.get(&page) {
+            return response.status(StatusCode::BAD_REQUEST).content_type("application/json").body(error.to_string());
+        }
+    }
+
+    let mut body = state.pages[page].clone();
+    if let Some(Value::Array(ads)) = body.get_mut("data") {
+        ads.retain(|ad| delivered_within(ad, query.get("ad_delivery_date_min"), query.get("ad_delivery_date_max")));
+        // Like the Graph API, only return the requested fields
+        if let Some(fields) = query.get("fields") {
+            let fields: Vec<&str> = fields.split(',').collect();
+            for ad in ads.iter_mut() {
+                if let Value::Object(ad) = ad {
+                    ad.retain(|field, _| fields.contains(&field.as_str()));
+                }
+            }
+        }
+    }
+    if page + 1 < state.pages.len() {
+        // Build cursor to the next page while keeping the rest of the query intact
+        let connection_info = req.connection_info();
+        let mut next = format!("{}://{}{}?after={}", connection_info.scheme(), connection_info.host(), req.path(), page + 1);
+        for pair in req.query_string().split('&').filter(|pair| !pair.is_empty() && !pair.starts_with("after=")) {
+            next.push('&');
+            next.push_str(pair);
+        }
+        body["paging"] = serde_json::json!({ "next": next });
+    } else {
+        body["paging"] = serde_json::json!({});
+    }
+
+    response.content_type("application/json").body(body.to_string())
+}
+
+pub async fn launch_mock_server(address: &str, fixture_dir: &str) -> Result<(), String> {
+    let state = web::Data::new(load_fixtures(fixture_dir)?);
+    println!("Serving {} page(s) from {}", state.pages.len(), fixture_dir);
+    HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .route("/{version}/ads_archive", web::get().to(ads_archive))
+    })
+    .bind(address).map_err(|_| "Failed to bind")?
+    .run()
+    .await.map_err(|_| "Failed to launch server")?;
+
+    Ok(())
+}
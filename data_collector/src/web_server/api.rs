@@ -1,11 +1,17 @@
-use actix_web::{web, HttpResponse, Responder, HttpRequest};
+use actix_web::{web, Either, HttpResponse, Responder, HttpRequest};
 
+use chrono::{DateTime, Utc};
+
+use serde::Deserialize;
+
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::fs;
 
 use std::process::Command;
 
 use crate::web_server::return_file;
+use crate::collector::{AdRecord, AdMetric, OpenRange, aggregate, collected_as_of, load_summary, load_records, RECORDS_FILE, REPORT_DATA_FILE};
 
 // Constants that point to python analysis scripts
 const SCRIPTS_FOLDER: &str = "../scripts/";
@@ -27,10 +33,147 @@ pub async fn get_corpus(req: HttpRequest, info: web::Path<String>) -> impl Respo
     return_file(&req, format!("web/data/{}/models/corpus_data.json", id))
 }
 
-// API endpoint to stats of ads by returning json file generated during ad collection
-pub async fn get_stats(req: HttpRequest, info: web::Path<String>) -> impl Responder {
+// Filters that can be applied to stats (eg. /explore/{id}/stats?platform=instagram)
+#[derive(Deserialize)]
+pub struct StatsFilter {
+    // Only include ads that ran on this publisher platform
+    platform: Option<String>,
+    // Estimate for open-ended impression ranges (eg. cap:5000000) ... see OpenRange
+    open_impressions: Option<String>,
+}
+
+impl StatsFilter {
+    fn open_range(&self) -> Result<OpenRange, actix_web::Error> {
+        match &self.open_impressions {
+            Some(open_impressions) => {
+                open_impressions.parse::<OpenRange>().map_err(|e| actix_web::Error::from(HttpResponse::BadRequest().body(e)))
+            },
+            None => Ok(OpenRange::default()),
+        }
+    }
+}
+
+// Loads the ads of a dataset that pass the filter along with when the whole dataset was collected
+//   The collection time comes from every ad so filtered stats judge active ads the same way as ad_data.json.
+fn filtered_records(id: &str, filter: &StatsFilter) -> Result<(HashMap<String, AdRecord>, DateTime<Utc>), actix_web::Error> {
+    let mut records = load_records(&format!("web/data/{}", id))
+                        .map_err(|_| actix_web::Error::from(HttpResponse::NotFound().body("Oops")))?;
+    let as_of = collected_as_of(&records);
+    if let Some(platform) = &filter.platform {
+        let platform = platform.to_lowercase();
+        records.retain(|_, record| record.publisher_platforms.contains(&platform));
+    }
+    Ok((records, as_of))
+}
+
+fn json_response<T: serde::Serialize>(value: &T) -> Result<HttpResponse, actix_web::Error> {
+    match serde_json::to_string(value) {
+        Ok(body) => Ok(HttpResponse::Ok().content_type("application/json").body(body)),
+        Err(_) => Err(actix_web::Error::from(HttpResponse::InternalServerError().body("Failed to serialize stats"))),
+    }
+}
+
+// API endpoint to stats (impressions and spend) of ads by returning json file generated during ad collection
+//   When filtered, stats are rebuilt from the dataset's ad records instead.
+pub async fn get_stats(req: HttpRequest, info: web::Path<String>, filter: web::Query<StatsFilter>) -> impl Responder {
     let id = &info;
-    return_file(&req, format!("web/data/{}/ad_data.json", id))
+    if filter.platform.is_none() && filter.open_impressions.is_none() {
+        // Imported reports keep their stats apart from ad_data.json
+        let report_path = PathBuf::from(format!("web/data/{}", id)).join(REPORT_DATA_FILE);
+        if report_path.exists() {
+            return Either::A(return_file(&req, report_path.display().to_string()));
+        }
+        return Either::A(return_file(&req, format!("web/data/{}/ad_data.json", id)));
+    }
+    Either::B(filter.open_range().and_then(|open_range| {
+        filtered_records(id, &filter).and_then(|(records, as_of)| json_response(&aggregate(&records, open_range, as_of)))
+    }))
+}
+
+// Values of an ad's attribute that stats can be grouped by ... ads missing the attribute are grouped under "Unknown"
+//   An ad that ran on several platforms belongs to the group of each platform.
+fn group_keys(record: &AdRecord, dimension: &str) -> Option<Vec<String>> {
+    let value = match dimension {
+        "page_id" => record.page_id.clone(),
+        // Fall back to the page id for records collected before page names were kept
+        "page_name" => record.page_name.clone().or_else(|| record.page_id.clone()),
+        "funding_entity" => record.funding_entity.clone(),
+        "country" => record.country.clone(),
+        "platform" if !record.publisher_platforms.is_empty() => {
+            return Some(record.publisher_platforms.iter().cloned().collect());
+        },
+        "platform" => None,
+        _ => return None,
+    };
+    Some(vec![value.unwrap_or_else(|| String::from("Unknown"))])
+}
+
+// Values of an aggregated entry's attribute that stats can be grouped by ... used for datasets without per-ad records
+fn metric_group_keys(metric: &AdMetric, dimension: &str) -> Option<Vec<String>> {
+    let values: Vec<String> = match dimension {
+        "page_id" => metric.page_ids.iter().cloned().collect(),
+        "page_name" => metric.page_names.iter().cloned().collect(),
+        "funding_entity" => metric.funding_entities.iter().cloned().collect(),
+        "country" => metric.countries.iter().cloned().collect(),
+        "platform" => metric.platform_ads.keys().cloned().collect(),
+        _ => return None,
+    };
+    if values.is_empty() {
+        Some(vec![String::from("Unknown")])
+    } else {
+        Some(values)
+    }
+}
+
+// Groups the entries of ad_data.json (or report_data.json) for datasets without per-ad records (eg. imported reports and the archives)
+//   Entries can't be split, so filters aren't supported and an entry with several values counts toward each group.
+fn grouped_data_stats(id: &str, dimension: &str, filter: &StatsFilter) -> Result<HttpResponse, actix_web::Error> {
+    if filter.platform.is_some() || filter.open_impressions.is_some() {
+        return Err(actix_web::Error::from(HttpResponse::BadRequest().body("Stats of datasets without per-ad records can't be filtered")));
+    }
+    let data = load_summary(&format!("web/data/{}", id))
+                .map_err(|_| actix_web::Error::from(HttpResponse::NotFound().body("Oops")))?;
+
+    let mut groups: HashMap<String, AdMetric> = HashMap::new();
+    for metric in data.into_values() {
+        let keys = match metric_group_keys(&metric, dimension) {
+            Some(keys) => keys,
+            None => {
+                return Err(actix_web::Error::from(HttpResponse::BadRequest().body("Stats can be grouped by page_id, page_name, funding_entity, country, or platform")));
+            },
+        };
+        for key in keys.into_iter() {
+            groups.entry(key).or_default().add(metric.clone());
+        }
+    }
+    json_response(&groups)
+}
+
+// API endpoint to get stats split by page, sponsor (funding entity), country, or platform
+//   Responds with a map from each group to the stats of its ads, in the same format as get_stats.
+pub async fn get_grouped_stats(info: web::Path<(String, String)>, filter: web::Query<StatsFilter>) -> impl Responder {
+    let id = &info.0;
+    let dimension = info.1.as_str();
+    if !PathBuf::from(format!("web/data/{}", id)).join(RECORDS_FILE).exists() {
+        return grouped_data_stats(id, dimension, &filter);
+    }
+    let open_range = filter.open_range()?;
+    let (records, as_of) = filtered_records(id, &filter)?;
+
+    let mut groups: HashMap<String, HashMap<String, AdRecord>> = HashMap::new();
+    for (ad_id, record) in records.into_iter() {
+        let keys = match group_keys(&record, dimension) {
+            Some(keys) => keys,
+            None => {
+                return Err(actix_web::Error::from(HttpResponse::BadRequest().body("Stats can be grouped by page_id, page_name, funding_entity, country, or platform")));
+            },
+        };
+        for key in keys.into_iter() {
+            groups.entry(key).or_default().insert(ad_id.clone(), record.clone());
+        }
+    }
+    let res: HashMap<String, _> = groups.iter().map(|(key, group)| (key.clone(), aggregate(group, open_range, as_of))).collect();
+    json_response(&res)
 }
 
 // API endpoint to find interesting words for a given model by executing python script
@@ -40,7 +183,7 @@ fn interesting_words(id: &String, num_best: usize) -> impl Responder {
     // Execute the script and the script will output json response
     let command = Command::new("python3")
         .current_dir(SCRIPTS_FOLDER)
-        .args(&[
+        .args([
             SIMILARITY_SCRIPT,
             "interesting_words",
             format!("{}", models_dir.as_path().display()).as_str(),
@@ -79,7 +222,7 @@ fn similar_docs(id: &String, doc_id: usize, num_best: usize) -> impl Responder {
     // Execute the script and the script will output json response
     let command = Command::new("python3")
         .current_dir(SCRIPTS_FOLDER)
-        .args(&[
+        .args([
             SIMILARITY_SCRIPT,
             "similar_docs",
             format!("{}", models_dir.as_path().display()).as_str(),
@@ -3,6 +3,11 @@ use actix_files::NamedFile;
 
 use askama::Template;
 
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::collector::{AdMetric, load_summary, REPORT_DATA_FILE};
+
 mod api;
 
 // Creates response containing file data
@@ -34,12 +39,81 @@ struct ExploreTemplate<'a> {
 
 // Creates response that lets the user explore the association graph for a given generated model
 async fn explore(id: web::Path<String>) -> impl Responder {
+    // Imported reports have no ad text to build a graph from ... show their spend instead
+    let dataset = PathBuf::from(format!("web/data/{}", id));
+    if dataset.join(REPORT_DATA_FILE).exists() && !dataset.join("association_graph.json").exists() {
+        return HttpResponse::Found().header("Location", format!("/report/{}", id)).finish();
+    }
     let render_html = ExploreTemplate {
         id: id.as_str()
     }.render().unwrap();
     HttpResponse::Ok().content_type("text/html").body(render_html)
 }
 
+// Row of the spend by page table
+struct PageSpend {
+    page_name: String,
+    page_id: String,
+    funding_entities: String,
+    spend: String,
+    ad_count: usize,
+}
+
+#[derive(Template)]
+#[template(path = "report.html")]
+struct ReportTemplate<'a> {
+    id: &'a str,
+    pages: Vec<PageSpend>,
+    total_ads: usize,
+    total_spend: String,
+}
+
+fn join(values: &std::collections::BTreeSet<String>) -> String {
+    values.iter().cloned().collect::<Vec<String>>().join(", ")
+}
+
+// Spend is a range when it is only known to fall in a bucket (eg. ≤100 in reports)
+fn format_spend(spend: (f64, f64), currency: &Option<String>) -> String {
+    let amount = if spend.0 == spend.1 { format!("{:.0}", spend.0) } else { format!("{:.0} - {:.0}", spend.0, spend.1) };
+    match currency {
+        Some(currency) => format!("{} {}", amount, currency),
+        None => amount,
+    }
+}
+
+// Creates response listing the spend and number of ads of every page ... works for imported reports and collections alike
+async fn report(id: web::Path<String>) -> impl Responder {
+    let data = match load_summary(&format!("web/data/{}", id)) {
+        Ok(data) => data,
+        Err(_) => return HttpResponse::NotFound().body("Oops"),
+    };
+
+    // Entries are split by disclaimer (or by ad text for collections) ... add them up per page
+    let mut pages: BTreeMap<String, AdMetric> = BTreeMap::new();
+    let mut total = AdMetric::default();
+    for metric in data.into_values() {
+        let page = if !metric.page_ids.is_empty() { join(&metric.page_ids) } else { join(&metric.page_names) };
+        total.add(metric.clone());
+        pages.entry(page).or_default().add(metric);
+    }
+    let mut pages: Vec<AdMetric> = pages.into_values().collect();
+    pages.sort_by(|a, b| b.spend.1.partial_cmp(&a.spend.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    let render_html = ReportTemplate {
+        id: id.as_str(),
+        pages: pages.iter().map(|page| PageSpend {
+            page_name: if page.page_names.is_empty() { String::from("Unknown") } else { join(&page.page_names) },
+            page_id: join(&page.page_ids),
+            funding_entities: join(&page.funding_entities),
+            spend: format_spend(page.spend, &page.currency),
+            ad_count: page.ad_count,
+        }).collect(),
+        total_ads: total.ad_count,
+        total_spend: format_spend(total.spend, &total.currency),
+    }.render().unwrap();
+    HttpResponse::Ok().content_type("text/html").body(render_html)
+}
+
 pub async fn launch_web_server(address: &str) -> Result<(), String> {
     HttpServer::new(|| {
         App::new()
@@ -48,9 +122,11 @@ pub async fn launch_web_server(address: &str) -> Result<(), String> {
             .route("/deps/{file_name}", web::get().to(retrieve_dependencies))
 
             .route("/explore/{id}", web::get().to(explore))
+            .route("/report/{id}", web::get().to(report))
             .route("/explore/{id}/graph", web::get().to(api::get_association_graph))
             .route("/explore/{id}/corpus", web::get().to(api::get_corpus))
             .route("/explore/{id}/stats", web::get().to(api::get_stats))
+            .route("/explore/{id}/stats/{dimension}", web::get().to(api::get_grouped_stats))
             .route("/explore/{id}/interesting_words/{num_best}", web::get().to(api::get_interesting_words))
             .route("/explore/{id}/similar_docs/{doc_id}/{num_best}", web::get().to(api::get_similar_docs))
     })
@@ -0,0 +1,95 @@
+// Helpers shared by the integration tests ... they drive the data_collector binary against the bundled mock Ad Library
+#![allow(dead_code)]
+
+use serde_json::Value;
+
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::Duration;
+
+pub const BIN: &str = env!("CARGO_BIN_EXE_data_collector");
+
+pub fn fixture_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("mock").join("ad_library")
+}
+
+// Empty directory under the system temp directory ... unique per test and process
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("data_collector_{}_{}", name, std::process::id()));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Mock Ad Library running on an ephemeral port ... killed when dropped
+pub struct MockServer {
+    child: Child,
+    pub base_url: String,
+}
+
+impl MockServer {
+    pub fn start(fixture_dir: &Path) -> MockServer {
+        // Let the OS pick a free port then hand it to the mock
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let address = format!("127.0.0.1:{}", port);
+        let child = Command::new(BIN)
+                        .args(["mock", address.as_str(), fixture_dir.to_str().unwrap()])
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .spawn()
+                        .unwrap();
+        let server = MockServer { child, base_url: format!("http://{}", address) };
+        for _ in 0..100 {
+            if TcpStream::connect(&address).is_ok() {
+                return server;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("Mock server did not start on {}", address);
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Runs the binary without an access token in the environment so only the tokens passed are used
+pub fn run(args: &[&str]) -> Output {
+    Command::new(BIN).args(args).env_remove("AD_LIBRARY_ACCESS_TOKEN").output().unwrap()
+}
+
+// Runs collect over the time frame covered by the bundled pages with waits kept short ... output of a failed run is returned as is
+pub fn try_collect(args: &[&str], save_path: &Path) -> Output {
+    let save_path = format!("--save_path={}", save_path.display());
+    let mut collect_args = vec!["collect", "--page_ids=1", "--year_start=2020", "--month_start=1", "--year_end=2020", "--month_end=3",
+                                "--retry_delay=0", "--backoff=0", save_path.as_str()];
+    collect_args.extend_from_slice(args);
+    run(&collect_args)
+}
+
+pub fn collect(args: &[&str], save_path: &Path) -> Output {
+    let output = try_collect(args, save_path);
+    assert!(output.status.success(), "collect failed:\n{}", String::from_utf8_lossy(&output.stdout));
+    output
+}
+
+pub fn read_json(path: &Path) -> Value {
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+// Records keyed by ad id without the time they were received, which differs between runs
+pub fn records_without_times(save_path: &Path) -> Value {
+    let mut records = read_json(&save_path.join("ad_records.json"));
+    for record in records.as_object_mut().unwrap().values_mut() {
+        record.as_object_mut().unwrap().remove("collected_at");
+    }
+    records
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use common::{MockServer, collect, fixture_dir, temp_dir};
+
+use std::fs;
+use std::path::Path;
+
+fn copy_fixtures(target: &Path) {
+    fs::create_dir_all(target).unwrap();
+    for entry in fs::read_dir(fixture_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(&path, target.join(path.file_name().unwrap())).unwrap();
+    }
+}
+
+// Collects fixtures once, then again incrementally on top of the first collection ... returns the output of the second run
+fn collect_twice(fixtures: &Path, dir: &Path) -> String {
+    let mock = MockServer::start(fixtures);
+    let base_url = format!("--base_url={}", mock.base_url);
+    let first = dir.join("first");
+    collect(&[base_url.as_str(), "--access_token=token"], &first);
+    let incremental = format!("--incremental={}", first.display());
+    let output = collect(&[base_url.as_str(), "--access_token=token", incremental.as_str()], &dir.join("second"));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn incremental_collection_stops_at_finished_ads() {
+    let dir = temp_dir("incremental_finished");
+    let output = collect_twice(&fixture_dir(), &dir);
+    assert!(output.contains("Reached ads that were already collected"), "{}", output);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Ads scheduled to stop after they were collected are still running ... their figures must be refreshed
+#[test]
+fn incremental_collection_pages_past_scheduled_stops() {
+    let dir = temp_dir("incremental_scheduled");
+    let fixtures = dir.join("fixtures");
+    copy_fixtures(&fixtures);
+    let page = fs::read_to_string(fixtures.join("1.json")).unwrap();
+    fs::write(fixtures.join("1.json"), page.replace("\"ad_delivery_stop_time\": \"2020-", "\"ad_delivery_stop_time\": \"2090-")).unwrap();
+
+    let output = collect_twice(&fixtures, &dir);
+    assert!(!output.contains("Reached ads that were already collected"), "{}", output);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use common::{MockServer, collect, fixture_dir, read_json, run, temp_dir, try_collect};
+
+use serde_json::{json, Value};
+
+use std::fs;
+
+// Runs collect → save_results against the bundled mock pages, which include a "reduce the amount of data" error
+// on page 1, an access token that expires after two requests, and an ad with a malformed percentage
+#[test]
+fn collects_bundled_pages_through_errors_and_token_expiry() {
+    let dir = temp_dir("mock_collection");
+    let output = {
+        let mock = MockServer::start(&fixture_dir());
+        let base_url = format!("--base_url={}", mock.base_url);
+        collect(&[base_url.as_str(), "--access_token=expiring-token", "--access_token=fresh-token"], &dir)
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // 1.error.json is served on the first request for page 1 ... the page is retried with half the page size
+    assert!(stdout.contains("Please reduce the amount of data"), "{}", stdout);
+    assert!(stdout.contains("Reducing page size to 500"), "{}", stdout);
+    // tokens.json expires the first token on its third request ... collection continues with the second one
+    assert!(stdout.contains("Access token #1 was rejected ... continuing with access token #2"), "{}", stdout);
+    assert!(stdout.contains("Access token #1 was used for 1 pages"), "{}", stdout);
+    assert!(stdout.contains("Access token #2 was used for 2 pages"), "{}", stdout);
+    assert!(stdout.contains("Collection finished: saved 7 ads"), "{}", stdout);
+
+    let records = read_json(&dir.join("ad_records.json"));
+    let records = records.as_object().unwrap();
+    let mut ids: Vec<&String> = records.keys().collect();
+    ids.sort();
+    assert_eq!(ids, ["2000000000000000", "2000000000000001", "2000000000000010", "2000000000000011",
+                     "2000000000000020", "2000000000000021", "2000000000000022"]);
+    for record in records.values() {
+        assert_eq!(record["country"], "US");
+        assert!(record["collected_at"].is_string());
+    }
+
+    let record = &records["2000000000000000"];
+    assert_eq!(record["page_id"], "111");
+    assert_eq!(record["ad_delivery_start_time"], "2020-03-28T00:00:00Z");
+    assert_eq!(record["ad_delivery_stop_time"], "2020-03-30T00:00:00Z");
+    assert_eq!(record["impressions"], json!([1000.0, 1999.0]));
+    assert_eq!(record["spend"], json!([0.0, 99.0]));
+    assert_eq!(record["demographic_distribution"]["female/25-34"], 0.4);
+    assert_eq!(record["publisher_platforms"], json!(["facebook", "instagram"]));
+
+    // Open-ended impressions (>1M) keep the lower bound as the upper bound by default
+    let record = &records["2000000000000001"];
+    assert_eq!(record["impressions"], json!([1000000.0, 1000000.0]));
+    assert_eq!(record["impressions_open_ended"], true);
+    assert_eq!(record["ad_delivery_stop_time"], Value::Null);
+
+    // Timestamps with an offset are accepted alongside bare dates
+    assert_eq!(records["2000000000000022"]["ad_delivery_start_time"], "2020-02-03T15:30:00Z");
+
+    let data = read_json(&dir.join("ad_data.json"));
+    let data = data.as_object().unwrap();
+    assert_eq!(data.len(), 6);
+    let ad_count: u64 = data.values().map(|metric| metric["ad_count"].as_u64().unwrap()).sum();
+    assert_eq!(ad_count, 7);
+    let spend: f64 = data.values().map(|metric| metric["spend"][1].as_f64().unwrap()).sum();
+    assert_eq!(spend, 99.0 + 49999.0 + 199.0 + 99.0 + 299.0 + 99.0 + 199.0);
+    // Ads 2000000000000000 and 2000000000000010 share their text
+    let shared: Vec<&Value> = data.values().filter(|metric| metric["ad_count"] == 2).collect();
+    assert_eq!(shared.len(), 1);
+    assert_eq!(shared[0]["spend"], json!([100.0, 298.0]));
+    assert_eq!(shared[0]["page_ids"], json!(["111"]));
+    let open_ended: Vec<&Value> = data.values().filter(|metric| metric["impressions_open_ended"] == true).collect();
+    assert_eq!(open_ended.len(), 1);
+    assert_eq!(open_ended[0]["open_ended_ads"], 1);
+
+    let rejects = read_json(&dir.join("rejects.json"));
+    let rejects = rejects.as_array().unwrap();
+    assert_eq!(rejects.len(), 1);
+    assert_eq!(rejects[0]["id"], "2000000000000023");
+    assert_eq!(rejects[0]["country"], "US");
+    assert_eq!(rejects[0]["reason"], "Invalid number for demographic_distribution: \"n/a\"");
+    assert_eq!(rejects[0]["ad"]["id"], "2000000000000023");
+
+    // Finished collections leave no checkpoint behind
+    assert!(!dir.join("checkpoint.json").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// A page size reduced after a "reduce the amount of data" error must survive in the checkpoint when collection
+// stops before the smaller page was fetched (here the only token expires on the retry)
+#[test]
+fn checkpoint_keeps_reduced_page_size() {
+    let dir = temp_dir("reduced_page_size");
+    let mock = MockServer::start(&fixture_dir());
+    let base_url = format!("--base_url={}", mock.base_url);
+
+    let output = try_collect(&[base_url.as_str(), "--access_token=expiring-token"], &dir);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success());
+    assert!(stdout.contains("Reducing page size to 500"), "{}", stdout);
+    assert!(stdout.contains("Saved 2 ads collected before stopping"), "{}", stdout);
+
+    let checkpoint = read_json(&dir.join("checkpoint.json"));
+    let endpoint = checkpoint["queries"][0]["endpoint"].as_str().unwrap();
+    assert!(endpoint.contains("after=1"), "{}", endpoint);
+    assert!(endpoint.contains("limit=500"), "{}", endpoint);
+    assert!(!endpoint.contains("expiring-token"), "{}", endpoint);
+
+    let save_path = format!("--save_path={}", dir.display());
+    let output = run(&["collect", "--resume", "--access_token=fresh-token", "--retry_delay=0", base_url.as_str(), save_path.as_str()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("limit=500"), "{}", stdout);
+    assert!(!stdout.contains("Reducing page size"), "{}", stdout);
+    assert!(stdout.contains("Collection finished: saved 7 ads"), "{}", stdout);
+
+    drop(mock);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// The mock returns every ad for every country ... ads are counted under the first country and the other is noted
+#[test]
+fn notes_every_country_an_ad_reached() {
+    let dir = temp_dir("reached_countries");
+    {
+        let mock = MockServer::start(&fixture_dir());
+        let base_url = format!("--base_url={}", mock.base_url);
+        collect(&[base_url.as_str(), "--access_token=token", "--countries=US,GB"], &dir);
+    }
+    let records = read_json(&dir.join("ad_records.json"));
+    assert_eq!(records.as_object().unwrap().len(), 7);
+    for record in records.as_object().unwrap().values() {
+        assert_eq!(record["country"], "US");
+        assert_eq!(record["reached_countries"], json!(["GB", "US"]));
+    }
+
+    // Rebuilding from the archive gives the same countries
+    let rebuilt = dir.join("rebuilt");
+    let output = run(&["reaggregate", dir.to_str().unwrap(), rebuilt.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    let rebuilt_records = read_json(&rebuilt.join("ad_records.json"));
+    for (id, record) in rebuilt_records.as_object().unwrap() {
+        assert_eq!(record["reached_countries"], records[id]["reached_countries"]);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
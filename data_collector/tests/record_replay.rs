@@ -0,0 +1,37 @@
+mod common;
+
+use common::{MockServer, collect, fixture_dir, read_json, records_without_times, temp_dir};
+
+use std::fs;
+
+// A recording made with a short access token must replay into the same records ... only access_token
+// parameters may be redacted, never the token's characters elsewhere in the body
+#[test]
+fn replaying_a_recording_gives_the_same_collection() {
+    let dir = temp_dir("record_replay");
+    let recording = dir.join("recording");
+    let recorded = dir.join("recorded");
+    let replayed = dir.join("replayed");
+
+    {
+        let mock = MockServer::start(&fixture_dir());
+        let base_url = format!("--base_url={}", mock.base_url);
+        let record = format!("--record={}", recording.display());
+        collect(&[base_url.as_str(), "--access_token=e", record.as_str()], &recorded);
+    }
+    for entry in fs::read_dir(&recording).unwrap() {
+        let content = fs::read_to_string(entry.unwrap().path()).unwrap();
+        assert!(!content.contains("access_token=e"), "token left in recording:\n{}", content);
+        assert!(!content.contains("REDACTEDn") && !content.contains("crREDACTED"), "body mangled by redaction:\n{}", content);
+    }
+
+    // Nothing listens on the base url ... every response must come from the recording
+    let replay = format!("--replay={}", recording.display());
+    collect(&["--base_url=http://127.0.0.1:9", replay.as_str()], &replayed);
+
+    assert!(!records_without_times(&recorded).as_object().unwrap().is_empty());
+    assert_eq!(records_without_times(&recorded), records_without_times(&replayed));
+    assert_eq!(read_json(&recorded.join("rejects.json")), read_json(&replayed.join("rejects.json")));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -16,11 +16,16 @@ function updateImpressionExplorer() {
         $("#graph").css("display", "none");
         $("#alternate_explore").text("Go Back To Graph View.");
 
-        const instructions = String.raw`
+        var instructions = String.raw`
         <li>The ad impressions statistics are broken up by demographic and region.</li>
         <li>Ad impressions are estimated, and a lower and upper bound is given for the demographics breakdown.</li>
         <li>Click on a bar/region to view the most seen ads for the demographic/region.</li>
         `;
+        // Warn when some figures include ads whose impressions were only reported as a lower bound
+        const censored_ads = Object.values(GLOBAL_STATE.ad_stats).filter(stats => stats["impressions_open_ended"]).length;
+        if (censored_ads > 0) {
+            instructions += `<li>${censored_ads} ad(s) fell in the open-ended impression bucket (eg. over 1M), so the upper bounds of their impressions are estimated and the figures are censored.</li>`;
+        }
     
         $("#doc_data").html(instructions);
     } else {
@@ -265,6 +270,12 @@ $(document).ready(function() {
         $("#stats_modal").css("display", "block");
 
         $("#doc_text").text(doc_text);
+        if (doc_stats["impressions_open_ended"]) {
+            $("#censored_note").text(`Impression figures are censored: ${doc_stats["open_ended_ads"]} ad(s) fell in the open-ended impression bucket (eg. over 1M), so their upper bound is estimated.`);
+            $("#censored_note").show();
+        } else {
+            $("#censored_note").hide();
+        }
         renderDemographicChart(demographic);
         renderRegionChart(region);
         populate_similar_docs_table(doc_id);